@@ -0,0 +1,172 @@
+use std::collections::{HashSet, VecDeque};
+use crate::sim::{Cell, Direction, Food, RivalBehaviour, SnakeSim, Tile};
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+
+// Picks the next direction for a computer controlled snake, None keeps going straight.
+// Decisions only depend on the state of the game, so a seed still replays the same game.
+pub fn choose_direction(sim: &SnakeSim, id: usize, behaviour: RivalBehaviour) -> Option<Direction> {
+    let snake = sim.snake(id);
+    if !snake.is_alive() {
+        return None;
+    }
+
+    let moves: Vec<(Direction, Cell)> = DIRECTIONS.iter()
+        .filter(|&&direction| snake.can_turn(direction))
+        .map(|&direction| (direction, sim.neighbour(snake.head(), direction)))
+        .filter(|&(_, cell)| is_free(sim, cell))
+        .collect();
+
+    let moves = match behaviour {
+        RivalBehaviour::Greedy | RivalBehaviour::Aggressive => moves,
+        RivalBehaviour::Cautious => {
+            // Prefer moves that leave room for the whole body and cannot end in a head-on collision
+            let roomy = prefer(moves, |&(_, cell)| reachable_area(sim, cell, snake.body().len()) >= snake.body().len());
+
+            prefer(roomy, |&(_, cell)| !next_to_other_head(sim, id, cell))
+        }
+    };
+
+    let target = match behaviour {
+        RivalBehaviour::Aggressive => intercept_target(sim, id),
+        _ => sim.mouse(),
+    };
+
+    // Poison is not worth going for, stay clear of it until it goes away
+    if sim.food() == Food::Poison && target == sim.mouse() {
+        return roomiest_move(sim, prefer(moves, |&(_, cell)| cell != sim.mouse()));
+    }
+
+    moves.into_iter()
+        .min_by_key(|&(_, cell)| distance(sim, cell, target))
+        .map(|(direction, _)| direction)
+}
+
+// Steers a player's snake along the shortest path to the mouse, as long as the first step leaves
+// enough room for the whole body. Otherwise, or when the mouse is poison, heads wherever there is
+// the most room to survive.
+pub fn autopilot_direction(sim: &SnakeSim, id: usize) -> Option<Direction> {
+    let snake = sim.snake(id);
+    if !snake.is_alive() {
+        return None;
+    }
+
+    let length = snake.body().len();
+    let wanted = sim.food() != Food::Poison;
+    if let Some(direction) = shortest_path(sim, id, sim.mouse()).filter(|_| wanted) {
+        let next = sim.neighbour(snake.head(), direction);
+        if reachable_area(sim, next, length) >= length {
+            return Some(direction);
+        }
+    }
+
+    let moves: Vec<(Direction, Cell)> = DIRECTIONS.iter()
+        .filter(|&&direction| snake.can_turn(direction))
+        .map(|&direction| (direction, sim.neighbour(snake.head(), direction)))
+        .filter(|&(_, cell)| is_free(sim, cell))
+        .collect();
+
+    roomiest_move(sim, prefer(moves, |&(_, cell)| wanted || cell != sim.mouse()))
+}
+
+// First step of the shortest path from the snake's head to the target over free cells
+pub fn shortest_path(sim: &SnakeSim, id: usize, target: Cell) -> Option<Direction> {
+    let snake = sim.snake(id);
+    let mut visited = HashSet::from([snake.head()]);
+    let mut queue = VecDeque::new();
+
+    for direction in DIRECTIONS {
+        let next = sim.neighbour(snake.head(), direction);
+        if snake.can_turn(direction) && is_free(sim, next) && visited.insert(next) {
+            queue.push_back((next, direction));
+        }
+    }
+
+    while let Some((cell, first)) = queue.pop_front() {
+        if cell == target {
+            return Some(first);
+        }
+
+        for direction in DIRECTIONS {
+            let next = sim.neighbour(cell, direction);
+            if is_free(sim, next) && visited.insert(next) {
+                queue.push_back((next, first));
+            }
+        }
+    }
+
+    None
+}
+
+// Number of free cells reachable from the given one, counting stops at the limit
+pub fn reachable_area(sim: &SnakeSim, from: Cell, limit: usize) -> usize {
+    let mut visited = HashSet::from([from]);
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(cell) = queue.pop_front() {
+        if visited.len() >= limit {
+            break;
+        }
+
+        for direction in DIRECTIONS {
+            let next = sim.neighbour(cell, direction);
+            if is_free(sim, next) && visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    visited.len()
+}
+
+// Steps needed to get from one cell to another on an empty board
+pub fn distance(sim: &SnakeSim, from: Cell, to: Cell) -> i32 {
+    sim.distance(from, to)
+}
+
+fn is_free(sim: &SnakeSim, cell: Cell) -> bool {
+    matches!(sim.grid().get(cell), Tile::Empty | Tile::Mouse | Tile::Bonus | Tile::PowerUp)
+}
+
+fn roomiest_move(sim: &SnakeSim, moves: Vec<(Direction, Cell)>) -> Option<Direction> {
+    moves.into_iter()
+        .max_by_key(|&(_, cell)| reachable_area(sim, cell, usize::MAX))
+        .map(|(direction, _)| direction)
+}
+
+// Keeps the moves matching the condition, unless that would leave none at all
+fn prefer<T>(moves: Vec<T>, condition: impl Fn(&T) -> bool) -> Vec<T> {
+    if moves.iter().any(&condition) {
+        moves.into_iter().filter(condition).collect()
+    } else {
+        moves
+    }
+}
+
+fn next_to_other_head(sim: &SnakeSim, id: usize, cell: Cell) -> bool {
+    sim.snakes().iter()
+        .enumerate()
+        .filter(|&(other, snake)| other != id && snake.is_alive())
+        .any(|(_, snake)| distance(sim, snake.head(), cell) <= 1)
+}
+
+// The cell right in front of the closest snake, or the mouse when that snake would not get there first
+fn intercept_target(sim: &SnakeSim, id: usize) -> Cell {
+    let head = sim.snake(id).head();
+    let mouse = sim.mouse();
+
+    let closest = sim.snakes().iter()
+        .enumerate()
+        .filter(|&(other, snake)| other != id && snake.is_alive())
+        .map(|(_, snake)| snake)
+        .min_by_key(|snake| distance(sim, head, snake.head()));
+
+    match closest {
+        Some(snake) if distance(sim, snake.head(), mouse) < distance(sim, head, mouse) => {
+            let ahead = sim.neighbour(snake.head(), snake.direction());
+
+            sim.neighbour(ahead, snake.direction())
+        }
+        _ => mouse,
+    }
+}
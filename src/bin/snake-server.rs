@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+use std::process;
+use clap::Parser;
+use snake::config::{Config, RulesArgs};
+use snake::net::{Server, DEFAULT_PORT};
+
+const DEFAULT_PLAYERS: usize = 4;
+
+#[derive(Parser)]
+#[command(about = "Runs snake games for players joining over the network")]
+struct Cli {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDRESS", default_value_t = format!("0.0.0.0:{}", DEFAULT_PORT))]
+    bind: String,
+
+    /// Path to a TOML config file, only its rules and input settings are used
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Seed of the first round, every following round adds one
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[command(flatten)]
+    rules: RulesArgs,
+
+    /// Number of player slots, the computer steers the snakes of the empty ones
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_PLAYERS)]
+    players: usize,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let config = load_config(&cli).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+    let seed = cli.seed.unwrap_or_else(rand::random);
+
+    let mut server = Server::bind(&cli.bind, config.rules, seed, config.input.max_queue_length).unwrap_or_else(|err| {
+        eprintln!("error: cannot listen on {}: {}", cli.bind, err);
+        process::exit(1);
+    });
+
+    println!("listening on {}", cli.bind);
+    server.run(|event| println!("{}", event));
+}
+
+fn load_config(cli: &Cli) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config = Config::load_or_default(cli.config.as_deref())?;
+    config.rules.players = cli.players;
+
+    // There is no asset server, so the level is read right away
+    config.apply_args(&cli.rules, true)?;
+
+    Ok(config)
+}
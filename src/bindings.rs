@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::sim::Direction;
+use crate::snake::{spawn_message_box, GameState};
+
+const BINDINGS_DIR: &str = "snake";
+const BINDINGS_FILE: &str = "bindings.toml";
+
+const SCREEN_SIZE: Vec2 = Vec2::new(640.0, 680.0);
+const SCREEN_FONT_SIZE: f32 = 17.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    TurnUp,
+    TurnDown,
+    TurnLeft,
+    TurnRight,
+    SecondTurnUp,
+    SecondTurnDown,
+    SecondTurnLeft,
+    SecondTurnRight,
+    Pause,
+    Restart,
+    Difficulty,
+    Autopilot,
+    Editor,
+    Leaderboard,
+    Bindings,
+    SaveReplay,
+    Confirm,
+    PlaybackStep,
+    PlaybackSpeed,
+    PlaybackRestart,
+    PlaceSpawn,
+    PlaceMouseSpot,
+    SaveLevel,
+    LoadLevel,
+    Quit,
+}
+
+// In the order of the rebinding screen. The turn keys also move around the other screens.
+pub const ACTIONS: [Action; 25] = [
+    Action::TurnUp,
+    Action::TurnDown,
+    Action::TurnLeft,
+    Action::TurnRight,
+    Action::SecondTurnUp,
+    Action::SecondTurnDown,
+    Action::SecondTurnLeft,
+    Action::SecondTurnRight,
+    Action::Pause,
+    Action::Restart,
+    Action::Difficulty,
+    Action::Autopilot,
+    Action::Editor,
+    Action::Leaderboard,
+    Action::Bindings,
+    Action::SaveReplay,
+    Action::Confirm,
+    Action::PlaybackStep,
+    Action::PlaybackSpeed,
+    Action::PlaybackRestart,
+    Action::PlaceSpawn,
+    Action::PlaceMouseSpot,
+    Action::SaveLevel,
+    Action::LoadLevel,
+    Action::Quit,
+];
+
+impl Action {
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::TurnUp => "PLAYER 1 UP",
+            Action::TurnDown => "PLAYER 1 DOWN",
+            Action::TurnLeft => "PLAYER 1 LEFT",
+            Action::TurnRight => "PLAYER 1 RIGHT",
+            Action::SecondTurnUp => "PLAYER 2 UP",
+            Action::SecondTurnDown => "PLAYER 2 DOWN",
+            Action::SecondTurnLeft => "PLAYER 2 LEFT",
+            Action::SecondTurnRight => "PLAYER 2 RIGHT",
+            Action::Pause => "START AND PAUSE",
+            Action::Restart => "RESTART",
+            Action::Difficulty => "DIFFICULTY",
+            Action::Autopilot => "AUTOPILOT",
+            Action::Editor => "LEVEL EDITOR",
+            Action::Leaderboard => "LEADERBOARD",
+            Action::Bindings => "KEY BINDINGS",
+            Action::SaveReplay => "SAVE REPLAY",
+            Action::Confirm => "CONFIRM",
+            Action::PlaybackStep => "REPLAY STEP",
+            Action::PlaybackSpeed => "REPLAY SPEED",
+            Action::PlaybackRestart => "REPLAY RESTART",
+            Action::PlaceSpawn => "EDITOR SPAWN",
+            Action::PlaceMouseSpot => "EDITOR MOUSE",
+            Action::SaveLevel => "EDITOR SAVE",
+            Action::LoadLevel => "EDITOR LOAD",
+            Action::Quit => "QUIT",
+        }
+    }
+
+    // The player and the direction of a turn. The second player's keys steer the first one in a single player game.
+    pub fn turn(self) -> Option<(usize, Direction)> {
+        match self {
+            Action::TurnUp => Some((0, Direction::Up)),
+            Action::TurnDown => Some((0, Direction::Down)),
+            Action::TurnLeft => Some((0, Direction::Left)),
+            Action::TurnRight => Some((0, Direction::Right)),
+            Action::SecondTurnUp => Some((1, Direction::Up)),
+            Action::SecondTurnDown => Some((1, Direction::Down)),
+            Action::SecondTurnLeft => Some((1, Direction::Left)),
+            Action::SecondTurnRight => Some((1, Direction::Right)),
+            _ => None,
+        }
+    }
+
+    fn default_keys(self) -> Vec<KeyCode> {
+        let key = match self {
+            Action::TurnUp => KeyCode::W,
+            Action::TurnDown => KeyCode::S,
+            Action::TurnLeft => KeyCode::A,
+            Action::TurnRight => KeyCode::D,
+            Action::SecondTurnUp => KeyCode::Up,
+            Action::SecondTurnDown => KeyCode::Down,
+            Action::SecondTurnLeft => KeyCode::Left,
+            Action::SecondTurnRight => KeyCode::Right,
+            Action::Pause => KeyCode::Space,
+            Action::Restart => KeyCode::R,
+            Action::Difficulty => KeyCode::Tab,
+            Action::Autopilot => KeyCode::T,
+            Action::Editor => KeyCode::E,
+            Action::Leaderboard => KeyCode::L,
+            Action::Bindings => KeyCode::B,
+            Action::SaveReplay => KeyCode::F2,
+            Action::Confirm => KeyCode::Return,
+            Action::PlaybackStep => KeyCode::N,
+            Action::PlaybackSpeed => KeyCode::F,
+            Action::PlaybackRestart => KeyCode::Home,
+            Action::PlaceSpawn => KeyCode::P,
+            Action::PlaceMouseSpot => KeyCode::M,
+            Action::SaveLevel => KeyCode::F5,
+            Action::LoadLevel => KeyCode::F9,
+            Action::Quit => KeyCode::Escape,
+        };
+
+        vec![key]
+    }
+}
+
+// The keys of every action, a key belongs to one action at most. Actions missing from the file keep their defaults.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bindings(BTreeMap<Action, Vec<KeyCode>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings(ACTIONS.iter().map(|&action| (action, action.default_keys())).collect())
+    }
+}
+
+// Where the bindings live, changes are not saved when it is not set
+#[derive(Resource, Clone, Debug)]
+pub struct BindingsPath(pub PathBuf);
+
+impl Bindings {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(BINDINGS_DIR).join(BINDINGS_FILE))
+    }
+
+    // A missing file is just the default bindings
+    pub fn load(path: &Path) -> Result<Bindings, BindingsError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Bindings::default()),
+            Err(err) => return Err(BindingsError::Read(path.to_path_buf(), err)),
+        };
+        let Bindings(loaded) = toml::from_str(&text).map_err(|err| BindingsError::Parse(path.to_path_buf(), err))?;
+
+        let mut bindings = Bindings::default();
+        bindings.0.extend(loaded);
+
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BindingsError> {
+        let text = toml::to_string(self).map_err(BindingsError::Serialize)?;
+
+        path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text))
+            .map_err(|err| BindingsError::Write(path.to_path_buf(), err))
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn just_pressed(&self, keys: &Input<KeyCode>, action: Action) -> bool {
+        keys.any_just_pressed(self.keys(action).iter().copied())
+    }
+
+    // Makes the key the only one of the action, taking it away from any other action
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        for keys in self.0.values_mut() {
+            keys.retain(|&other| other != key);
+        }
+
+        self.0.insert(action, vec![key]);
+    }
+
+    pub fn reset(&mut self, action: Action) {
+        let defaults = action.default_keys();
+        for keys in self.0.values_mut() {
+            keys.retain(|key| !defaults.contains(key));
+        }
+
+        self.0.insert(action, defaults);
+    }
+
+    // The keys of the action for help texts, e.g. "W OR UP"
+    pub fn keys_text(&self, action: Action) -> String {
+        let keys: Vec<String> = self.keys(action).iter().map(|key| format!("{:?}", key).to_uppercase()).collect();
+
+        if keys.is_empty() { String::from("UNBOUND") } else { keys.join(" OR ") }
+    }
+
+    // The turn keys of both players for the direction, e.g. "A OR LEFT"
+    pub fn direction_text(&self, direction: Direction) -> String {
+        let keys: Vec<String> = ACTIONS.iter()
+            .filter(|action| action.turn().is_some_and(|(_, turn)| turn == direction))
+            .map(|&action| self.keys_text(action))
+            .collect();
+
+        keys.join(" OR ")
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Read(path, err) => write!(f, "cannot read key bindings {}: {}", path.display(), err),
+            BindingsError::Write(path, err) => write!(f, "cannot write key bindings {}: {}", path.display(), err),
+            BindingsError::Parse(path, err) => write!(f, "invalid key bindings {}: {}", path.display(), err),
+            BindingsError::Serialize(err) => write!(f, "cannot serialize key bindings: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+// The keyboard as seen through the bindings
+#[derive(SystemParam)]
+pub(crate) struct Actions<'w> {
+    keys: Res<'w, Input<KeyCode>>,
+    bindings: Res<'w, Bindings>,
+}
+
+impl Actions<'_> {
+    pub(crate) fn just_pressed(&self, action: Action) -> bool {
+        self.bindings.just_pressed(&self.keys, action)
+    }
+
+    // Turns that were just pressed, by player and direction
+    pub(crate) fn turns(&self) -> Vec<(usize, Direction)> {
+        ACTIONS.iter().filter(|&&action| self.just_pressed(action)).filter_map(|action| action.turn()).collect()
+    }
+
+    // Directions just pressed by any player, for moving around screens
+    pub(crate) fn directions(&self) -> Vec<Direction> {
+        self.turns().into_iter().map(|(_, direction)| direction).collect()
+    }
+}
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Bindings), setup_screen)
+            .add_systems(OnExit(GameState::Bindings), (save_bindings, despawn_screen))
+            .add_systems(Update, (rebind, update_screen).chain().run_if(in_state(GameState::Bindings)));
+    }
+}
+
+#[derive(Resource)]
+struct Rebinding {
+    selected: usize,
+    listening: bool,
+}
+
+#[derive(Component)]
+struct Screen;
+
+#[derive(Component)]
+struct ScreenText;
+
+fn setup_screen(mut commands: Commands, config: Res<Config>) {
+    commands.insert_resource(Rebinding { selected: 0, listening: false });
+
+    spawn_message_box(&mut commands, &config, SCREEN_SIZE, SCREEN_FONT_SIZE, String::new(), Screen, ScreenText);
+}
+
+// The screen has fixed keys of its own, so broken bindings can always be fixed
+fn rebind(
+    keys: Res<Input<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let action = ACTIONS[rebinding.selected];
+
+    if rebinding.listening {
+        if keys.just_pressed(KeyCode::Escape) {
+            rebinding.listening = false;
+        } else if let Some(&key) = keys.get_just_pressed().next() {
+            bindings.bind(action, key);
+            rebinding.listening = false;
+        }
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Up) {
+        rebinding.selected = (rebinding.selected + ACTIONS.len() - 1) % ACTIONS.len();
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        rebinding.selected = (rebinding.selected + 1) % ACTIONS.len();
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        rebinding.listening = true;
+    }
+    if keys.any_just_pressed([KeyCode::Delete, KeyCode::Back]) {
+        bindings.reset(action);
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Startup);
+    }
+}
+
+fn update_screen(
+    rebinding: Res<Rebinding>,
+    bindings: Res<Bindings>,
+    mut query: Query<&mut Text, With<ScreenText>>,
+) {
+    if !rebinding.is_changed() && !bindings.is_changed() {
+        return;
+    }
+
+    let rows: Vec<String> = ACTIONS.iter()
+        .enumerate()
+        .map(|(i, &action)| {
+            let keys = if i == rebinding.selected && rebinding.listening {
+                String::from("PRESS A KEY")
+            } else {
+                bindings.keys_text(action)
+            };
+            let marker = if i == rebinding.selected { ">" } else { " " };
+
+            format!("{} {:<16} {:>16}", marker, action.label(), keys)
+        })
+        .collect();
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "KEY BINDINGS\n\n{}\n\nUP/DOWN: SELECT  ENTER: CHANGE  DELETE: DEFAULT\nESC: {}",
+            rows.join("\n"),
+            if rebinding.listening { "CANCEL" } else { "SAVE AND GO BACK" },
+        );
+    }
+}
+
+fn save_bindings(bindings: Res<Bindings>, path: Option<Res<BindingsPath>>) {
+    if let Some(path) = path {
+        if let Err(err) = bindings.save(&path.0) {
+            warn!("{}", err);
+        }
+    }
+}
+
+fn despawn_screen(mut commands: Commands, query: Query<Entity, With<Screen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
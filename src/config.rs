@@ -0,0 +1,317 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use bevy::prelude::*;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use crate::level::Level;
+use crate::sim::{DifficultyPreset, GameMode, MouseBehaviour, RivalBehaviour, Rules};
+
+const BLOCK_SIZE: f32 = 20.0;
+const MAX_INPUT_QUEUE_LENGTH: usize = 2;
+const STICK_DEAD_ZONE: f32 = 0.5;
+
+const WALL_COLOR: Rgb = Rgb([0.8, 0.8, 0.8]);
+const MOUSE_COLOR: Rgb = Rgb([1.0, 0.65, 0.34]);
+const GOLDEN_MOUSE_COLOR: Rgb = Rgb([1.0, 0.85, 0.1]);
+const POISON_COLOR: Rgb = Rgb([0.45, 0.9, 0.2]);
+const SPEED_UP_COLOR: Rgb = Rgb([1.0, 0.25, 0.25]);
+const SLOW_DOWN_COLOR: Rgb = Rgb([0.3, 0.6, 1.0]);
+const BONUS_COLOR: Rgb = Rgb([0.95, 0.35, 0.95]);
+const GHOST_COLOR: Rgb = Rgb([0.75, 0.75, 0.9]);
+const SLOW_MOTION_COLOR: Rgb = Rgb([0.2, 0.9, 0.9]);
+const MAGNET_COLOR: Rgb = Rgb([0.9, 0.1, 0.5]);
+const SHIELD_COLOR: Rgb = Rgb([0.2, 0.4, 0.9]);
+const SNAKE_COLOR: Rgb = Rgb([1.0, 1.0, 1.0]);
+const SECOND_SNAKE_COLOR: Rgb = Rgb([1.0, 1.0, 0.4]);
+const RIVAL_COLOR: Rgb = Rgb([1.0, 0.4, 1.0]);
+const SCOREBOARD_COLOR: Rgb = Rgb([1.0, 1.0, 1.0]);
+const MESSAGE_BOX_BACKGROUND_COLOR: Rgb = Rgb([1.0, 1.0, 1.0]);
+const MESSAGE_BOX_TEXT_COLOR: Rgb = Rgb([0.0, 0.0, 0.0]);
+const BACKGROUND_COLOR: Rgb = Rgb([0.1, 0.1, 0.1]);
+
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub level: Option<String>,
+    pub rules: Rules,
+    pub display: DisplayConfig,
+    pub colors: ColorConfig,
+    pub input: InputConfig,
+    pub audio: AudioConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub block_size: f32,
+    pub fullscreen: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig { block_size: BLOCK_SIZE, fullscreen: false }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    pub wall: Rgb,
+    pub mouse: Rgb,
+    pub golden_mouse: Rgb,
+    pub poison: Rgb,
+    pub speed_up: Rgb,
+    pub slow_down: Rgb,
+    pub bonus: Rgb,
+    pub ghost: Rgb,
+    pub slow_motion: Rgb,
+    pub magnet: Rgb,
+    pub shield: Rgb,
+    pub snake: Rgb,
+    pub second_snake: Rgb,
+    pub rival: Rgb,
+    pub scoreboard: Rgb,
+    pub message_box_background: Rgb,
+    pub message_box_text: Rgb,
+    pub background: Rgb,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        ColorConfig {
+            wall: WALL_COLOR,
+            mouse: MOUSE_COLOR,
+            golden_mouse: GOLDEN_MOUSE_COLOR,
+            poison: POISON_COLOR,
+            speed_up: SPEED_UP_COLOR,
+            slow_down: SLOW_DOWN_COLOR,
+            bonus: BONUS_COLOR,
+            ghost: GHOST_COLOR,
+            slow_motion: SLOW_MOTION_COLOR,
+            magnet: MAGNET_COLOR,
+            shield: SHIELD_COLOR,
+            snake: SNAKE_COLOR,
+            second_snake: SECOND_SNAKE_COLOR,
+            rival: RIVAL_COLOR,
+            scoreboard: SCOREBOARD_COLOR,
+            message_box_background: MESSAGE_BOX_BACKGROUND_COLOR,
+            message_box_text: MESSAGE_BOX_TEXT_COLOR,
+            background: BACKGROUND_COLOR,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    pub max_queue_length: usize,
+    pub autopilot: bool,
+    pub stick_dead_zone: f32,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            max_queue_length: MAX_INPUT_QUEUE_LENGTH,
+            autopilot: false,
+            stick_dead_zone: STICK_DEAD_ZONE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub muted: bool,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Rgb(pub [f32; 3]);
+
+impl From<Rgb> for Color {
+    fn from(rgb: Rgb) -> Self {
+        let [r, g, b] = rgb.0;
+
+        Color::rgb(r, g, b)
+    }
+}
+
+// Command line options that change the rules, the same for the game and the server
+#[derive(Args, Clone, Debug, Default)]
+pub struct RulesArgs {
+    /// Board size in cells, both even, e.g. 40x22
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_board)]
+    pub board: Option<(i32, i32)>,
+
+    /// Level to play, a path inside the assets directory, e.g. levels/pillars.level
+    #[arg(long, value_name = "PATH")]
+    pub level: Option<String>,
+
+    /// Game mode
+    #[arg(long)]
+    pub mode: Option<GameMode>,
+
+    /// Number of computer controlled rival snakes
+    #[arg(long, value_name = "COUNT")]
+    pub rivals: Option<usize>,
+
+    /// How the computer plays: greedy, cautious or aggressive
+    #[arg(long, value_name = "BEHAVIOUR")]
+    pub rival_behaviour: Option<RivalBehaviour>,
+
+    /// How the mice move: still, wandering, skittish or sly
+    #[arg(long, value_name = "BEHAVIOUR")]
+    pub mouse_behaviour: Option<MouseBehaviour>,
+
+    /// Speed curve, minimum tick and difficulty steps: easy, normal, hard or insane
+    #[arg(long, value_name = "PRESET")]
+    pub preset: Option<DifficultyPreset>,
+
+    /// Difficulty level every game starts at
+    #[arg(long, value_name = "LEVEL")]
+    pub difficulty: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Level(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "cannot read config {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "cannot parse config {}: {}", path.display(), err),
+            ConfigError::Level(reason) => write!(f, "{}", reason),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|err| ConfigError::Read(path.to_path_buf(), err))?;
+        let config: Config = toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // The config at the path, or the defaults when there is none
+    pub fn load_or_default(path: Option<&Path>) -> Result<Config, ConfigError> {
+        path.map_or_else(|| Ok(Config::default()), Config::load)
+    }
+
+    // Puts the command line options over the config. Without an asset server around to load the level
+    // later, it has to be read right away.
+    pub fn apply_args(&mut self, args: &RulesArgs, load_level: bool) -> Result<(), ConfigError> {
+        if let Some((width, height)) = args.board {
+            self.rules.board.width = width;
+            self.rules.board.height = height;
+        }
+        if let Some(level) = &args.level {
+            self.level = Some(level.clone());
+        }
+        if let Some(mode) = args.mode {
+            self.rules.mode = mode;
+        }
+        if let Some(rivals) = args.rivals {
+            self.rules.rivals = rivals;
+        }
+        if let Some(behaviour) = args.rival_behaviour {
+            self.rules.rival_behaviour = behaviour;
+        }
+        if let Some(behaviour) = args.mouse_behaviour {
+            self.rules.mouse_behaviour = behaviour;
+        }
+        if let Some(preset) = args.preset {
+            preset.apply(&mut self.rules);
+        }
+        if let Some(difficulty) = args.difficulty {
+            self.rules.starting_difficulty = difficulty;
+        }
+
+        if load_level {
+            if let Some(level) = &self.level {
+                Level::load(level).map_err(ConfigError::Level)?.apply(&mut self.rules);
+            }
+        }
+
+        self.validate()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.rules.validate().map_err(|reason| ConfigError::Invalid(format!("rules: {}", reason)))?;
+
+        if !self.display.block_size.is_finite() || self.display.block_size <= 0.0 {
+            return Err(ConfigError::Invalid(String::from("display.block_size must be positive")));
+        }
+        if self.input.max_queue_length == 0 {
+            return Err(ConfigError::Invalid(String::from("input.max_queue_length must be at least 1")));
+        }
+        if !(0.0..1.0).contains(&self.input.stick_dead_zone) {
+            return Err(ConfigError::Invalid(String::from("input.stick_dead_zone must be between 0 and 1")));
+        }
+
+        let colors = [
+            ("wall", self.colors.wall),
+            ("mouse", self.colors.mouse),
+            ("golden_mouse", self.colors.golden_mouse),
+            ("poison", self.colors.poison),
+            ("speed_up", self.colors.speed_up),
+            ("slow_down", self.colors.slow_down),
+            ("bonus", self.colors.bonus),
+            ("ghost", self.colors.ghost),
+            ("slow_motion", self.colors.slow_motion),
+            ("magnet", self.colors.magnet),
+            ("shield", self.colors.shield),
+            ("snake", self.colors.snake),
+            ("second_snake", self.colors.second_snake),
+            ("rival", self.colors.rival),
+            ("scoreboard", self.colors.scoreboard),
+            ("message_box_background", self.colors.message_box_background),
+            ("message_box_text", self.colors.message_box_text),
+            ("background", self.colors.background),
+        ];
+        for (name, color) in colors {
+            if color.0.iter().any(|c| !(0.0..=1.0).contains(c)) {
+                return Err(ConfigError::Invalid(format!("colors.{} components must be between 0 and 1", name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn block_scale(&self) -> Vec3 {
+        Vec3::new(self.display.block_size, self.display.block_size, 1.0)
+    }
+
+    // Size of the whole arena in pixels, walls included
+    pub fn arena_size(&self) -> Vec2 {
+        let board = self.rules.board;
+
+        Vec2::new(
+            (2 * board.half_width() + 1) as f32,
+            (2 * board.half_height() + 1) as f32,
+        ) * self.display.block_size
+    }
+}
+
+fn parse_board(value: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("expected WIDTHxHEIGHT, got `{}`", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+
+    Ok((
+        width.parse().map_err(|_| invalid())?,
+        height.parse().map_err(|_| invalid())?,
+    ))
+}
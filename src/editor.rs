@@ -0,0 +1,308 @@
+use std::fs;
+use std::path::Path;
+use bevy::{prelude::*, window::PrimaryWindow};
+use crate::bindings::{Action, Actions, Bindings};
+use crate::config::Config;
+use crate::level::{Level, ASSETS_PATH};
+use crate::sim::{Cell, GameMode};
+use crate::snake::{snake_color, BlockBundle, GameComponents, GameState, WallBundle, WallLocation};
+
+const DEFAULT_LEVEL_PATH: &str = "levels/custom.level";
+
+const EDITOR_FONT_SIZE: f32 = 18.0;
+const EDITOR_PADDING: Val = Val::Px(10.0);
+const BUTTON_SIZE: Vec2 = Vec2::new(140.0, 40.0);
+const BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Editor), setup_editor)
+            .add_systems(OnExit(GameState::Editor), cleanup_editor)
+            .add_systems(Update, (
+                edit_level,
+                save_or_load_level,
+                test_play,
+                draw_level,
+                update_status,
+            ).chain().run_if(in_state(GameState::Editor)));
+    }
+}
+
+#[derive(Resource)]
+struct Editor {
+    level: Level,
+    path: String,
+    status: String,
+}
+
+#[derive(Component)]
+struct EditorBlock;
+
+#[derive(Component)]
+struct EditorStatus;
+
+#[derive(Component)]
+struct TestPlayButton;
+
+fn setup_editor(mut commands: Commands, config: Res<Config>, bindings: Res<Bindings>) {
+    let path = config.level.clone().unwrap_or_else(|| String::from(DEFAULT_LEVEL_PATH));
+
+    commands.insert_resource(Editor {
+        level: Level::from_rules(&config.rules),
+        status: format!("EDITING {}", path),
+        path,
+    });
+
+    let text_style = TextStyle {
+        font_size: EDITOR_FONT_SIZE,
+        color: config.colors.scoreboard.into(),
+        ..default()
+    };
+
+    // Status
+    commands.spawn((
+        TextBundle::from_section("", text_style.clone()).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: EDITOR_PADDING,
+            left: EDITOR_PADDING,
+            ..default()
+        }),
+        EditorStatus,
+        GameComponents,
+    ));
+
+    // Help
+    let help = format!(
+        "LEFT CLICK: WALL  RIGHT CLICK: ERASE  {}: MOUSE SPOT\n{}: SNAKE SPAWN  TURN KEYS: SNAKE DIRECTION\n{}: SAVE  {}: LOAD  {}: TEST PLAY",
+        bindings.keys_text(Action::PlaceMouseSpot),
+        bindings.keys_text(Action::PlaceSpawn),
+        bindings.keys_text(Action::SaveLevel),
+        bindings.keys_text(Action::LoadLevel),
+        bindings.keys_text(Action::Confirm),
+    );
+    commands.spawn((
+        TextBundle::from_section(help, text_style.clone()).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: EDITOR_PADDING,
+            left: EDITOR_PADDING,
+            ..default()
+        }),
+        GameComponents,
+    ));
+
+    // Test play button
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: EDITOR_PADDING,
+                    right: EDITOR_PADDING,
+                    width: Val::Px(BUTTON_SIZE.x),
+                    height: Val::Px(BUTTON_SIZE.y),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            TestPlayButton,
+            GameComponents,
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section("TEST PLAY", text_style));
+        });
+}
+
+fn edit_level(
+    mut editor: ResMut<Editor>,
+    config: Res<Config>,
+    actions: Actions,
+    buttons: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    interaction_query: Query<&Interaction, With<TestPlayButton>>,
+) {
+    let mut level = editor.level.clone();
+
+    if let Some(&direction) = actions.directions().first() {
+        level.direction = direction;
+    }
+
+    // Clicks on the button should not paint the cells underneath it
+    let over_button = interaction_query.iter().any(|interaction| *interaction != Interaction::None);
+
+    let cursor = cursor_cell(&window_query, &camera_query, &config)
+        .filter(|&cell| !over_button && level.board.is_inside(cell));
+
+    if let Some(cell) = cursor {
+        let is_spawn = cell == level.spawn || level.extra_spawns.iter().any(|spawn| spawn.position == cell);
+
+        if buttons.pressed(MouseButton::Left) && !is_spawn && !level.walls.contains(&cell) {
+            level.mouse_spots.retain(|&spot| spot != cell);
+            level.walls.push(cell);
+        }
+        if buttons.pressed(MouseButton::Right) {
+            level.walls.retain(|&wall| wall != cell);
+            level.mouse_spots.retain(|&spot| spot != cell);
+            level.extra_spawns.retain(|spawn| spawn.position != cell);
+        }
+        if actions.just_pressed(Action::PlaceSpawn) {
+            level.walls.retain(|&wall| wall != cell);
+            level.mouse_spots.retain(|&spot| spot != cell);
+            level.extra_spawns.retain(|spawn| spawn.position != cell);
+            level.spawn = cell;
+        }
+        if actions.just_pressed(Action::PlaceMouseSpot) && !is_spawn && !level.walls.contains(&cell) {
+            if level.mouse_spots.contains(&cell) {
+                level.mouse_spots.retain(|&spot| spot != cell);
+            } else {
+                level.mouse_spots.push(cell);
+            }
+        }
+    }
+
+    // Only touch the resource on actual edits, so the level is not redrawn every frame
+    if level != editor.level {
+        editor.level = level;
+    }
+}
+
+fn cursor_cell(
+    window_query: &Query<&Window, With<PrimaryWindow>>,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+    config: &Config,
+) -> Option<Cell> {
+    let cursor = window_query.get_single().ok()?.cursor_position()?;
+    let (camera, transform) = camera_query.get_single().ok()?;
+    let position = camera.viewport_to_world_2d(transform, cursor)? / config.display.block_size;
+
+    Some(Cell::new(position.x.round() as i32, position.y.round() as i32))
+}
+
+fn save_or_load_level(mut editor: ResMut<Editor>, mut config: ResMut<Config>, actions: Actions) {
+    let path = Path::new(ASSETS_PATH).join(&editor.path);
+
+    if actions.just_pressed(Action::SaveLevel) {
+        let saved = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, editor.level.to_text()));
+
+        editor.status = match saved {
+            Ok(()) => format!("SAVED {}", editor.path),
+            Err(err) => format!("CANNOT SAVE {}: {}", editor.path, err),
+        };
+    }
+
+    if actions.just_pressed(Action::LoadLevel) {
+        let loaded = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Level::parse(&text));
+
+        match loaded {
+            Ok(level) => {
+                // Borders are drawn from the config, keep them in line with the loaded level
+                config.rules.board = level.board;
+                editor.level = level;
+                editor.status = format!("LOADED {}", editor.path);
+            }
+            Err(reason) => editor.status = format!("CANNOT LOAD {}: {}", editor.path, reason),
+        }
+    }
+}
+
+fn test_play(
+    mut editor: ResMut<Editor>,
+    mut config: ResMut<Config>,
+    mut next_state: ResMut<NextState<GameState>>,
+    actions: Actions,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<TestPlayButton>)>,
+) {
+    let clicked = interaction_query.iter().any(|interaction| *interaction == Interaction::Pressed);
+    if !clicked && !actions.just_pressed(Action::Confirm) {
+        return;
+    }
+
+    let mut rules = config.rules.clone();
+    editor.level.apply(&mut rules);
+
+    match rules.validate() {
+        Ok(()) => {
+            config.rules = rules;
+            next_state.set(GameState::Running);
+        }
+        Err(reason) => editor.status = format!("CANNOT PLAY: {}", reason),
+    }
+}
+
+fn draw_level(
+    mut commands: Commands,
+    editor: Res<Editor>,
+    config: Res<Config>,
+    block_query: Query<Entity, With<EditorBlock>>,
+    mut button_query: Query<(&Interaction, &mut BackgroundColor), With<TestPlayButton>>,
+) {
+    for (interaction, mut color) in button_query.iter_mut() {
+        *color = match interaction {
+            Interaction::None => BUTTON_COLOR.into(),
+            _ => BUTTON_HOVERED_COLOR.into(),
+        };
+    }
+
+    if !editor.is_changed() {
+        return;
+    }
+
+    for entity in &block_query {
+        commands.entity(entity).despawn();
+    }
+
+    let level = &editor.level;
+    let block_size = config.block_scale();
+
+    if config.rules.mode != GameMode::Wrap {
+        for location in [WallLocation::Left, WallLocation::Top, WallLocation::Right, WallLocation::Bottom] {
+            commands.spawn((WallBundle::new(location, &config), EditorBlock));
+        }
+    }
+    for &cell in &level.walls {
+        commands.spawn((WallBundle::new(WallLocation::Block(cell), &config), EditorBlock));
+    }
+    for &cell in &level.mouse_spots {
+        commands.spawn((
+            BlockBundle::new(config.colors.mouse.into(), cell.into(), block_size),
+            EditorBlock,
+            GameComponents,
+        ));
+    }
+
+    // Show the whole starting snakes, so it is clear where they go and whether they fit
+    let mut rules = config.rules.clone();
+    level.apply(&mut rules);
+
+    let delta = 1.0 / rules.snake_starting_length as f32;
+    for snake in 0..rules.snake_count() {
+        for (i, cell) in rules.snake_starting_cells(snake).enumerate() {
+            let color = snake_color(&config, snake, delta * i as f32);
+
+            commands.spawn((BlockBundle::new(color, cell.into(), block_size), EditorBlock, GameComponents));
+        }
+    }
+}
+
+fn update_status(editor: Res<Editor>, mut query: Query<&mut Text, With<EditorStatus>>) {
+    if !editor.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = editor.status.clone();
+    }
+}
+
+fn cleanup_editor(mut commands: Commands) {
+    commands.remove_resource::<Editor>();
+}
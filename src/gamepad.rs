@@ -0,0 +1,100 @@
+use bevy::{
+    input::{gamepad::{GamepadConnection, GamepadConnectionEvent}, InputSystem},
+    prelude::*,
+};
+use crate::config::Config;
+use crate::sim::Direction;
+use crate::snake::GameState;
+
+const DPAD: [(GamepadButtonType, Direction); 4] = [
+    (GamepadButtonType::DPadUp, Direction::Up),
+    (GamepadButtonType::DPadDown, Direction::Down),
+    (GamepadButtonType::DPadLeft, Direction::Left),
+    (GamepadButtonType::DPadRight, Direction::Right),
+];
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Pads>()
+            .add_event::<PadTurn>()
+            .add_systems(PreUpdate, (track_pads, read_pads).chain().after(InputSystem));
+    }
+}
+
+// Gamepads in the order they were plugged in, the first one steers the first player and so on
+#[derive(Resource, Default)]
+pub(crate) struct Pads(Vec<Pad>);
+
+struct Pad {
+    gamepad: Gamepad,
+    stick: Option<Direction>,
+}
+
+#[derive(Event)]
+pub(crate) struct PadTurn {
+    pub pad: usize,
+    pub direction: Direction,
+}
+
+pub(crate) fn any_just_pressed(buttons: &Input<GamepadButton>, button_types: &[GamepadButtonType]) -> bool {
+    buttons.get_just_pressed().any(|button| button_types.contains(&button.button_type))
+}
+
+fn track_pads(
+    mut pads: ResMut<Pads>,
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for event in connection_events.read() {
+        match event.connection {
+            GamepadConnection::Connected(_) => {
+                if pads.0.iter().all(|pad| pad.gamepad != event.gamepad) {
+                    pads.0.push(Pad { gamepad: event.gamepad, stick: None });
+                }
+            }
+            GamepadConnection::Disconnected => {
+                pads.0.retain(|pad| pad.gamepad != event.gamepad);
+
+                // Losing a controller mid-game should not lose the game
+                if *state.get() == GameState::Running {
+                    next_state.set(GameState::Paused);
+                }
+            }
+        }
+    }
+}
+
+// The stick only turns when it is pushed into a new direction, holding it does not fill the queue
+fn read_pads(
+    mut pads: ResMut<Pads>,
+    config: Res<Config>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut turns: EventWriter<PadTurn>,
+) {
+    for (i, pad) in pads.0.iter_mut().enumerate() {
+        for (button_type, direction) in DPAD {
+            if buttons.just_pressed(GamepadButton::new(pad.gamepad, button_type)) {
+                turns.send(PadTurn { pad: i, direction });
+            }
+        }
+
+        let x = axes.get(GamepadAxis::new(pad.gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.0);
+        let y = axes.get(GamepadAxis::new(pad.gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.0);
+        let stick = if x.abs().max(y.abs()) < config.input.stick_dead_zone {
+            None
+        } else if x.abs() > y.abs() {
+            Some(if x > 0.0 { Direction::Right } else { Direction::Left })
+        } else {
+            Some(if y > 0.0 { Direction::Up } else { Direction::Down })
+        };
+
+        if let Some(direction) = stick.filter(|&direction| pad.stick != Some(direction)) {
+            turns.send(PadTurn { pad: i, direction });
+        }
+        pad.stick = stick;
+    }
+}
@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use crate::bindings::{Action, Actions, Bindings};
+use crate::config::Config;
+use crate::scores::{format_date, HighScores, Table};
+use crate::sim::Direction;
+use crate::snake::{spawn_message_box, Game, GameState};
+
+const NAME_LENGTH: usize = 3;
+const LETTERS: u8 = 26;
+const NAME_ENTRY_SIZE: Vec2 = Vec2::new(500.0, 300.0);
+const NAME_ENTRY_FONT_SIZE: f32 = 30.0;
+
+const LEADERBOARD_SIZE: Vec2 = Vec2::new(760.0, 520.0);
+const LEADERBOARD_FONT_SIZE: f32 = 20.0;
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::NameEntry), setup_name_entry)
+            .add_systems(OnExit(GameState::NameEntry), despawn_screen)
+            .add_systems(Update, (enter_name, update_name_entry).chain().run_if(in_state(GameState::NameEntry)))
+            .add_systems(OnEnter(GameState::Leaderboard), setup_leaderboard)
+            .add_systems(OnExit(GameState::Leaderboard), despawn_screen)
+            .add_systems(Update, (browse_leaderboard, update_leaderboard).chain().run_if(in_state(GameState::Leaderboard)));
+    }
+}
+
+// The name for the high score of the game that just ended
+#[derive(Resource)]
+pub(crate) struct EnteredName(pub String);
+
+#[derive(Resource)]
+struct NameEntry {
+    letters: [u8; NAME_LENGTH],
+    cursor: usize,
+}
+
+#[derive(Resource)]
+struct LeaderboardView {
+    tables: Vec<Table>,
+    index: usize,
+}
+
+#[derive(Component)]
+struct Screen;
+
+#[derive(Component)]
+struct ScreenText;
+
+fn setup_name_entry(mut commands: Commands, config: Res<Config>) {
+    commands.insert_resource(NameEntry { letters: [b'A'; NAME_LENGTH], cursor: 0 });
+
+    spawn_message_box(&mut commands, &config, NAME_ENTRY_SIZE, NAME_ENTRY_FONT_SIZE, String::new(), Screen, ScreenText);
+}
+
+fn enter_name(
+    mut commands: Commands,
+    actions: Actions,
+    mut entry: ResMut<NameEntry>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let cursor = entry.cursor;
+
+    for direction in actions.directions() {
+        match direction {
+            Direction::Up => entry.letters[cursor] = shift_letter(entry.letters[cursor], 1),
+            Direction::Down => entry.letters[cursor] = shift_letter(entry.letters[cursor], LETTERS - 1),
+            Direction::Left => entry.cursor = cursor.saturating_sub(1),
+            Direction::Right => entry.cursor = (cursor + 1).min(NAME_LENGTH - 1),
+        }
+    }
+
+    if actions.just_pressed(Action::Confirm) {
+        commands.insert_resource(EnteredName(String::from_utf8_lossy(&entry.letters).into_owned()));
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn shift_letter(letter: u8, by: u8) -> u8 {
+    b'A' + (letter - b'A' + by) % LETTERS
+}
+
+fn update_name_entry(
+    entry: Res<NameEntry>,
+    game: Res<Game>,
+    bindings: Res<Bindings>,
+    mut query: Query<&mut Text, With<ScreenText>>,
+) {
+    if !entry.is_changed() {
+        return;
+    }
+
+    // The letter being changed is the one in brackets
+    let name: Vec<String> = entry.letters.iter()
+        .enumerate()
+        .map(|(i, &letter)| if i == entry.cursor { format!("[{}]", letter as char) } else { format!(" {} ", letter as char) })
+        .collect();
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "NEW HIGH SCORE: {}\nENTER YOUR NAME\n\n{}\n\n{}/{}: LETTER\n{}/{}: MOVE\n{}: DONE",
+            game.score(),
+            name.concat(),
+            bindings.direction_text(Direction::Up),
+            bindings.direction_text(Direction::Down),
+            bindings.direction_text(Direction::Left),
+            bindings.direction_text(Direction::Right),
+            bindings.keys_text(Action::Confirm),
+        );
+    }
+}
+
+fn setup_leaderboard(mut commands: Commands, config: Res<Config>, game: Res<Game>, high_scores: Res<HighScores>) {
+    // Start at the table of the current game, even when it has no scores yet
+    let current = Table::from(game.rules());
+    let mut tables = high_scores.tables();
+    let index = tables.iter().position(|&table| table == current).unwrap_or_else(|| {
+        tables.insert(0, current);
+        0
+    });
+
+    commands.insert_resource(LeaderboardView { tables, index });
+
+    spawn_message_box(&mut commands, &config, LEADERBOARD_SIZE, LEADERBOARD_FONT_SIZE, String::new(), Screen, ScreenText);
+}
+
+fn browse_leaderboard(actions: Actions, mut view: ResMut<LeaderboardView>) {
+    let count = view.tables.len();
+
+    for direction in actions.directions() {
+        match direction {
+            Direction::Left => view.index = (view.index + count - 1) % count,
+            Direction::Right => view.index = (view.index + 1) % count,
+            _ => {}
+        }
+    }
+}
+
+fn update_leaderboard(
+    view: Res<LeaderboardView>,
+    high_scores: Res<HighScores>,
+    bindings: Res<Bindings>,
+    mut query: Query<&mut Text, With<ScreenText>>,
+) {
+    if !view.is_changed() {
+        return;
+    }
+
+    let table = view.tables[view.index];
+    let rows: Vec<String> = high_scores.table(table)
+        .enumerate()
+        .map(|(i, entry)| format!(
+            "{:>2}. {:<3} {:>7}  LENGTH {:>3}  DIFFICULTY {:>2}  {}",
+            i + 1,
+            if entry.name.is_empty() { "???" } else { &entry.name },
+            entry.score,
+            entry.length,
+            entry.difficulty,
+            format_date(entry.timestamp),
+        ))
+        .collect();
+    let rows = if rows.is_empty() { String::from("NO SCORES YET") } else { rows.join("\n") };
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "LEADERBOARD {} ({}/{})\n\n{}\n\n{}/{}: OTHER BOARDS  {} OR {}: BACK",
+            table.to_string().to_uppercase(),
+            view.index + 1,
+            view.tables.len(),
+            rows,
+            bindings.direction_text(Direction::Left),
+            bindings.direction_text(Direction::Right),
+            bindings.keys_text(Action::Leaderboard),
+            bindings.keys_text(Action::Pause),
+        );
+    }
+}
+
+fn despawn_screen(mut commands: Commands, query: Query<Entity, With<Screen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use crate::sim::{Board, Cell, Direction, Rules, Spawn};
+
+pub const ASSETS_PATH: &str = "assets";
+
+const WALL: char = '#';
+const EMPTY: char = '.';
+const MOUSE_SPOT: char = '*';
+
+// A level describes the playable area only, the border around it comes from the game mode.
+// Rows and columns map to cells centered around (0, 0), so both dimensions have to be odd.
+// The first spawn in reading order belongs to the first snake, any further ones to the other snakes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub board: Board,
+    pub walls: Vec<Cell>,
+    pub spawn: Cell,
+    pub direction: Direction,
+    pub mouse_spots: Vec<Cell>,
+    pub extra_spawns: Vec<Spawn>,
+}
+
+impl Level {
+    // Reads a level straight from the assets directory, for when there is no asset server around
+    pub fn load(level: &str) -> Result<Level, String> {
+        let path = Path::new(ASSETS_PATH).join(level);
+        let text = fs::read_to_string(&path).map_err(|err| format!("cannot read level {}: {}", path.display(), err))?;
+
+        Level::parse(&text).map_err(|reason| format!("invalid level {}: {}", path.display(), reason))
+    }
+
+    pub fn parse(text: &str) -> Result<Level, String> {
+        let rows: Vec<Vec<char>> = text.lines()
+            .map(|line| line.trim_end().chars().collect())
+            .filter(|row: &Vec<char>| !row.is_empty())
+            .collect();
+
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 {
+            return Err(String::from("level is empty"));
+        }
+        if let Some(i) = rows.iter().position(|row| row.len() != width) {
+            return Err(format!("row {} is {} cells wide, expected {}", i + 1, rows[i].len(), width));
+        }
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(format!("level must have an odd number of rows and columns, got {}x{}", width, height));
+        }
+
+        let board = Board { width: width as i32 + 1, height: height as i32 + 1 };
+        let mut walls = Vec::new();
+        let mut mouse_spots = Vec::new();
+        let mut spawns = Vec::new();
+
+        for (row, line) in rows.iter().enumerate() {
+            for (column, &c) in line.iter().enumerate() {
+                let cell = Cell::new(
+                    column as i32 - board.half_width() + 1,
+                    board.half_height() - 1 - row as i32,
+                );
+
+                let direction = match c {
+                    WALL => {
+                        walls.push(cell);
+                        continue;
+                    }
+                    MOUSE_SPOT => {
+                        mouse_spots.push(cell);
+                        continue;
+                    }
+                    EMPTY | ' ' => continue,
+                    '>' => Direction::Right,
+                    '<' => Direction::Left,
+                    '^' => Direction::Up,
+                    'v' => Direction::Down,
+                    _ => return Err(format!("unknown cell `{}` at row {}, column {}", c, row + 1, column + 1)),
+                };
+
+                spawns.push(Spawn { position: cell, direction });
+            }
+        }
+
+        if spawns.is_empty() {
+            return Err(String::from("level has no snake spawn, mark it with > < ^ or v"));
+        }
+        let first = spawns.remove(0);
+
+        Ok(Level {
+            board,
+            walls,
+            spawn: first.position,
+            direction: first.direction,
+            mouse_spots,
+            extra_spawns: spawns,
+        })
+    }
+
+    pub fn from_rules(rules: &Rules) -> Level {
+        Level {
+            board: rules.board,
+            walls: rules.walls.clone(),
+            spawn: rules.snake_starting_position,
+            direction: rules.snake_starting_direction,
+            mouse_spots: rules.mouse_spots.clone(),
+            extra_spawns: rules.extra_spawns.clone(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let x_pos = self.board.half_width() - 1;
+        let y_pos = self.board.half_height() - 1;
+
+        let mut text = String::new();
+        for y in (-y_pos..=y_pos).rev() {
+            for x in -x_pos..=x_pos {
+                let cell = Cell::new(x, y);
+                let spawn = if cell == self.spawn {
+                    Some(self.direction)
+                } else {
+                    self.extra_spawns.iter().find(|spawn| spawn.position == cell).map(|spawn| spawn.direction)
+                };
+
+                text.push(if let Some(direction) = spawn {
+                    match direction {
+                        Direction::Right => '>',
+                        Direction::Left => '<',
+                        Direction::Up => '^',
+                        Direction::Down => 'v',
+                    }
+                } else if self.walls.contains(&cell) {
+                    WALL
+                } else if self.mouse_spots.contains(&cell) {
+                    MOUSE_SPOT
+                } else {
+                    EMPTY
+                });
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    pub fn apply(&self, rules: &mut Rules) {
+        rules.board = self.board;
+        rules.walls = self.walls.clone();
+        rules.mouse_spots = self.mouse_spots.clone();
+        rules.snake_starting_position = self.spawn;
+        rules.snake_starting_direction = self.direction;
+        rules.extra_spawns = self.extra_spawns.clone();
+    }
+}
+
+#[derive(Asset, TypePath, Deref)]
+pub struct LevelAsset(pub Level);
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Read(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Read(err) => write!(f, "cannot read level: {}", err),
+            LevelError::Parse(reason) => write!(f, "invalid level: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = LevelError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<LevelAsset, LevelError>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await.map_err(LevelError::Read)?;
+
+            Level::parse(&text).map(LevelAsset).map_err(LevelError::Parse)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
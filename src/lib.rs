@@ -1 +1,13 @@
+pub mod ai;
+pub mod bindings;
+pub mod config;
+pub mod editor;
+pub mod gamepad;
+pub mod leaderboard;
+pub mod level;
+pub mod net;
+pub mod online;
+pub mod replay;
+pub mod scores;
+pub mod sim;
 pub mod snake;
@@ -1,9 +1,228 @@
-use bevy::prelude::*;
-use snake::snake::SnakeApp;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use bevy::{
+    app::AppExit,
+    input::InputPlugin,
+    prelude::*,
+    time::TimeUpdateStrategy,
+    window::WindowMode,
+};
+use clap::Parser;
+use snake::bindings::{Bindings, BindingsPath};
+use snake::config::{Config, RulesArgs};
+use snake::online::OnlineSession;
+use snake::replay::{Playback, Recording, Replay};
+use snake::scores::{HighScores, HighScoresPath};
+use snake::sim::SnakeSim;
+use snake::snake::{Game, GameState, Seed, SnakeApp};
+
+const DEFAULT_CONFIG_PATH: &str = "snake.toml";
+const HEADLESS_FRAME_DURATION: Duration = Duration::from_millis(1);
+const KEYBOARD_PLAYERS: usize = 2;
+
+#[derive(Parser)]
+#[command(about = "A classic snake game")]
+struct Cli {
+    /// Path to a TOML config file, snake.toml is used when present
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Seed for every random decision, the same seed and inputs replay the same game
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[command(flatten)]
+    rules: RulesArgs,
+
+    /// Size of a single cell in pixels
+    #[arg(long, value_name = "PIXELS")]
+    block_size: Option<f32>,
+
+    /// Number of players sharing the keyboard, the second one steers with the arrow keys
+    #[arg(long, value_name = "COUNT")]
+    players: Option<usize>,
+
+    /// Run in a window, even when the config asks for fullscreen
+    #[arg(long, conflicts_with = "fullscreen")]
+    windowed: bool,
+
+    /// Run fullscreen, even when the config asks for a window
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Let a bot steer the first snake, toggle it in game with T
+    #[arg(long)]
+    autopilot: bool,
+
+    /// Disable all sounds
+    #[arg(long)]
+    mute: bool,
+
+    /// Run without a window, start right away and print the result on game over
+    #[arg(long, conflicts_with_all = ["windowed", "fullscreen"])]
+    headless: bool,
+
+    /// Save a replay of the game to this file on game over
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Play back a replay file instead of playing, with --headless only print its result
+    #[arg(long, value_name = "PATH", conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    /// Join the game of a snake-server, e.g. localhost:7878
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["headless", "record", "replay"])]
+    connect: Option<String>,
+
+    /// Name shown to the other players of an online game
+    #[arg(long, default_value = "")]
+    name: String,
+}
 
 fn main() {
-    App::new()
-        .add_plugins((DefaultPlugins, SnakeApp))
-        .add_systems(Update, bevy::window::close_on_esc)
-        .run();
+    let cli = Cli::parse();
+
+    let mut config = load_config(&cli).unwrap_or_else(|err| exit_with_error(err));
+
+    let replay = cli.replay.as_ref().map(|path| Replay::load(path).unwrap_or_else(|err| exit_with_error(err.into())));
+
+    // Replays carry their own rules, levels included, and do not need a window to get to the result
+    if let Some(replay) = &replay {
+        if cli.headless {
+            print_result(&replay.play_to(replay.ticks));
+            return;
+        }
+
+        config.rules = replay.rules.clone();
+        config.level = None;
+    }
+
+    // Online games are played by the rules of the server
+    let session = cli.connect.as_ref().map(|address| {
+        OnlineSession::join(address, &cli.name).unwrap_or_else(|err| exit_with_error(err.into()))
+    });
+    if let Some(session) = &session {
+        config.rules = session.rules().clone();
+        config.level = None;
+    } else if config.rules.players > KEYBOARD_PLAYERS {
+        exit_with_error(format!("at most {} players can share a keyboard", KEYBOARD_PLAYERS).into());
+    }
+
+    let mut app = App::new();
+    app.insert_resource(config);
+
+    if let Some(session) = session {
+        app.insert_resource(session)
+            .add_systems(Startup, start_online);
+    } else if let Some(replay) = replay {
+        app.insert_resource(Seed(replay.seed))
+            .insert_resource(Playback::new(replay))
+            .add_systems(Startup, start_playback);
+    } else if let Some(seed) = cli.seed {
+        app.insert_resource(Seed(seed));
+    }
+
+    if let Some(path) = cli.record.clone() {
+        app.add_systems(OnEnter(GameState::GameOver), move |recording: Res<Recording>| {
+            if let Err(err) = recording.save(&path) {
+                eprintln!("error: {}", err);
+            }
+        });
+    }
+
+    if cli.headless {
+        app.add_plugins((MinimalPlugins, InputPlugin, SnakeApp))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(HEADLESS_FRAME_DURATION))
+            .add_systems(Startup, start_game)
+            .add_systems(OnEnter(GameState::GameOver), report_and_exit);
+    } else {
+        if let Some(path) = HighScores::default_path() {
+            app.insert_resource(HighScoresPath(path));
+        }
+        if let Some(path) = Bindings::default_path() {
+            app.insert_resource(BindingsPath(path));
+        }
+
+        let mode = if app.world.resource::<Config>().display.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+
+        app.add_plugins((
+            DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: String::from("Snake"),
+                    mode,
+                    ..default()
+                }),
+                ..default()
+            }),
+            SnakeApp,
+        ));
+    }
+
+    app.run();
+}
+
+fn load_config(cli: &Cli) -> Result<Config, Box<dyn std::error::Error>> {
+    // An explicitly given config has to exist, the default one is optional
+    let default_path = Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|path| path.exists());
+    let mut config = Config::load_or_default(cli.config.clone().or(default_path).as_deref())?;
+
+    if let Some(block_size) = cli.block_size {
+        config.display.block_size = block_size;
+    }
+    if let Some(players) = cli.players {
+        config.rules.players = players;
+    }
+    if cli.windowed || cli.fullscreen {
+        config.display.fullscreen = cli.fullscreen;
+    }
+    config.input.autopilot |= cli.autopilot;
+    config.audio.muted |= cli.mute || cli.headless;
+
+    // Without an asset server the level has to be applied before the game starts
+    config.apply_args(&cli.rules, cli.headless)?;
+
+    Ok(config)
+}
+
+fn print_result(game: &SnakeSim) {
+    println!("score: {} difficulty: {} seed: {}", game.score(), game.difficulty(), game.seed());
+
+    let players = &game.snakes()[..game.rules().players];
+    if players.len() > 1 {
+        let scores: Vec<String> = players.iter()
+            .enumerate()
+            .map(|(i, snake)| format!("player {}: {}", i + 1, snake.score()))
+            .collect();
+        let winner = game.winner().map_or_else(|| String::from("draw"), |i| format!("player {}", i + 1));
+
+        println!("{} winner: {}", scores.join(" "), winner);
+    }
+}
+
+fn exit_with_error(err: Box<dyn std::error::Error>) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(1);
+}
+
+fn start_game(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Running);
+}
+
+fn start_playback(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Replay);
+}
+
+fn start_online(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Online);
+}
+
+fn report_and_exit(game: Res<Game>, mut exit: EventWriter<AppExit>) {
+    print_result(&game);
+
+    exit.send(AppExit);
 }
@@ -0,0 +1,511 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use crate::ai;
+use crate::sim::{Direction, Rules, SnakeSim, Snapshot};
+
+pub const DEFAULT_PORT: u16 = 7878;
+// Goes up once a released client or server could no longer understand the other side, i.e. when the
+// messages change shape, which includes the rules sent along with the welcome and the snapshots
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Clients ping at least this often, a connection that stays quiet for the timeout is gone
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+const READ_BUFFER_SIZE: usize = 4096;
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+const MAX_BACKLOG: usize = 1024 * 1024;
+
+const MAX_NAME_LENGTH: usize = 12;
+const ROUND_RESTART_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Join { version: u32, name: String },
+    Turn(Direction),
+    Ping,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    // The slot is the snake the client steers
+    Welcome { slot: usize, rules: Rules },
+    Refused(String),
+    // The names of the players by slot, the computer steers the snakes of the empty ones
+    Players(Vec<Option<String>>),
+    Snapshot { round: usize, tick: usize, state: Snapshot },
+}
+
+// A non-blocking connection carrying messages as RON, one per line
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+    last_heard: Instant,
+}
+
+impl Connection {
+    // The port can be left out of the address, the default one is used then
+    pub fn connect(address: &str) -> Result<Connection, NetError> {
+        let address = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, DEFAULT_PORT) };
+        let socket_address = address.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| NetError::Protocol(format!("cannot resolve {}", address)))?;
+
+        Connection::new(TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)?)
+    }
+
+    pub fn new(stream: TcpStream) -> Result<Connection, NetError> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+
+        Ok(Connection {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+            last_heard: Instant::now(),
+        })
+    }
+
+    pub fn is_timed_out(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_heard) > TIMEOUT
+    }
+
+    // Whatever the socket does not take right away is kept for the next flush,
+    // a peer that falls too far behind is given up on
+    pub fn send<T: Serialize>(&mut self, message: &T) -> Result<(), NetError> {
+        let line = ron::to_string(message).map_err(|err| NetError::Protocol(err.to_string()))?;
+        self.outgoing.extend_from_slice(line.as_bytes());
+        self.outgoing.push(b'\n');
+
+        if self.outgoing.len() > MAX_BACKLOG {
+            return Err(NetError::Backlog);
+        }
+
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> Result<(), NetError> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(NetError::Closed),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Every message that arrived so far, never blocks
+    pub fn receive<T: DeserializeOwned>(&mut self, now: Instant) -> Result<Vec<T>, NetError> {
+        self.fill(now)?;
+
+        let mut messages = Vec::new();
+        loop {
+            match self.next() {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => return Ok(messages),
+                // Hand out what came before the peer hung up, the next call reports it
+                Err(NetError::Closed) if !messages.is_empty() => return Ok(messages),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Blocks until the next message, only meant for setting up a connection
+    pub fn wait<T: DeserializeOwned>(&mut self, timeout: Duration) -> Result<T, NetError> {
+        let start = Instant::now();
+
+        loop {
+            self.fill(Instant::now())?;
+            if let Some(message) = self.next()? {
+                return Ok(message);
+            }
+            if start.elapsed() > timeout {
+                return Err(NetError::TimedOut);
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn fill(&mut self, now: Instant) -> Result<(), NetError> {
+        let mut buffer = [0; READ_BUFFER_SIZE];
+
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(read) => {
+                    self.incoming.extend_from_slice(&buffer[..read]);
+                    self.last_heard = now;
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next<T: DeserializeOwned>(&mut self) -> Result<Option<T>, NetError> {
+        let Some(end) = self.incoming.iter().position(|&byte| byte == b'\n') else {
+            if self.incoming.len() > MAX_MESSAGE_LENGTH {
+                return Err(NetError::Protocol(String::from("message is too long")));
+            }
+
+            return if self.closed { Err(NetError::Closed) } else { Ok(None) };
+        };
+
+        let line: Vec<u8> = self.incoming.drain(..=end).collect();
+        let text = std::str::from_utf8(&line).map_err(|err| NetError::Protocol(err.to_string()))?;
+
+        ron::from_str(text.trim_end()).map(Some).map_err(|err| NetError::Protocol(err.to_string()))
+    }
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    Closed,
+    TimedOut,
+    Backlog,
+    Protocol(String),
+    Refused(String),
+}
+
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> Self {
+        NetError::Io(err)
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "connection failed: {}", err),
+            NetError::Closed => write!(f, "connection closed"),
+            NetError::TimedOut => write!(f, "connection timed out"),
+            NetError::Backlog => write!(f, "connection is too slow"),
+            NetError::Protocol(reason) => write!(f, "invalid message: {}", reason),
+            NetError::Refused(reason) => write!(f, "refused by the server: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+// What happened on the server, for whoever runs it to report
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerEvent {
+    AcceptFailed(String),
+    Joined { name: String, slot: usize },
+    Left { name: String, slot: usize, reason: String },
+    RoundStarted { round: usize, seed: u64 },
+    RoundOver { round: usize, ticks: usize, scores: Vec<usize> },
+}
+
+impl fmt::Display for ServerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerEvent::AcceptFailed(err) => write!(f, "cannot accept a connection: {}", err),
+            ServerEvent::Joined { name, slot } => write!(f, "{} joined as player {}", name, slot + 1),
+            ServerEvent::Left { name, slot, reason } => write!(f, "{} as player {} left: {}", name, slot + 1, reason),
+            ServerEvent::RoundStarted { round, seed } => write!(f, "round {} started with seed {}", round, seed),
+            ServerEvent::RoundOver { round, ticks, scores } => {
+                let scores: Vec<String> = scores.iter().map(usize::to_string).collect();
+                write!(f, "round {} over after {} ticks, scores: {}", round, ticks, scores.join(" "))
+            }
+        }
+    }
+}
+
+struct Client {
+    connection: Connection,
+    slot: Option<usize>,
+    name: String,
+    queue: VecDeque<Direction>,
+    gone: Option<String>,
+}
+
+enum Phase {
+    Waiting,
+    Playing { next_step: Instant },
+    Over { restart_at: Instant },
+}
+
+// Runs the game for everybody connected. Every player slot belongs to at most one client,
+// the computer steers the snakes of the free ones. Rounds go on for as long as anybody is around.
+pub struct Server {
+    listener: TcpListener,
+    rules: Rules,
+    seed: u64,
+    max_queue_length: usize,
+    clients: Vec<Client>,
+    game: SnakeSim,
+    round: usize,
+    tick: usize,
+    phase: Phase,
+    events: Vec<ServerEvent>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A, rules: Rules, seed: u64, max_queue_length: usize) -> io::Result<Server> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Server {
+            listener,
+            game: SnakeSim::new(rules.clone(), seed),
+            rules,
+            seed,
+            max_queue_length,
+            clients: Vec::new(),
+            round: 0,
+            tick: 0,
+            phase: Phase::Waiting,
+            events: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn game(&self) -> &SnakeSim {
+        &self.game
+    }
+
+    pub fn run(&mut self, mut on_event: impl FnMut(ServerEvent)) -> ! {
+        loop {
+            self.update(Instant::now()).into_iter().for_each(&mut on_event);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // Does whatever is due at the given time and tells what happened, never blocks
+    pub fn update(&mut self, now: Instant) -> Vec<ServerEvent> {
+        self.accept();
+        self.receive(now);
+        self.advance(now);
+
+        for client in &mut self.clients {
+            if let Err(err) = client.connection.flush() {
+                client.gone.get_or_insert(err.to_string());
+            }
+        }
+        self.remove_gone();
+
+        std::mem::take(&mut self.events)
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match Connection::new(stream) {
+                    Ok(connection) => self.clients.push(Client {
+                        connection,
+                        slot: None,
+                        name: String::new(),
+                        queue: VecDeque::new(),
+                        gone: None,
+                    }),
+                    Err(err) => self.events.push(ServerEvent::AcceptFailed(err.to_string())),
+                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    self.events.push(ServerEvent::AcceptFailed(err.to_string()));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self, now: Instant) {
+        for i in 0..self.clients.len() {
+            let messages = match self.clients[i].connection.receive(now) {
+                Ok(messages) => messages,
+                Err(err) => {
+                    self.clients[i].gone.get_or_insert(err.to_string());
+                    continue;
+                }
+            };
+
+            for message in messages {
+                match message {
+                    ClientMessage::Join { version, name } => self.join(i, version, &name),
+                    ClientMessage::Turn(direction) => {
+                        let client = &mut self.clients[i];
+                        if client.slot.is_some() && client.queue.len() < self.max_queue_length {
+                            client.queue.push_back(direction);
+                        }
+                    }
+                    ClientMessage::Ping => {}
+                }
+            }
+
+            if self.clients[i].connection.is_timed_out(now) {
+                self.clients[i].gone.get_or_insert(NetError::TimedOut.to_string());
+            }
+        }
+    }
+
+    fn join(&mut self, i: usize, version: u32, name: &str) {
+        if self.clients[i].slot.is_some() {
+            return;
+        }
+
+        let free = (0..self.rules.players).find(|&slot| self.clients.iter().all(|client| client.slot != Some(slot)));
+        let refusal = if version != PROTOCOL_VERSION {
+            Some(format!("protocol version {} is not supported, the server speaks {}", version, PROTOCOL_VERSION))
+        } else if free.is_none() {
+            Some(String::from("the server is full"))
+        } else {
+            None
+        };
+
+        let snapshot = self.snapshot();
+        let client = &mut self.clients[i];
+        if let Some(reason) = refusal {
+            let _ = client.connection.send(&ServerMessage::Refused(reason.clone()));
+            client.gone = Some(reason);
+            return;
+        }
+
+        let slot = free.unwrap();
+        let name: String = name.trim().chars().filter(|c| !c.is_control()).take(MAX_NAME_LENGTH).collect();
+        client.slot = Some(slot);
+        client.name = if name.is_empty() { format!("PLAYER {}", slot + 1) } else { name.to_uppercase() };
+        self.events.push(ServerEvent::Joined { name: client.name.clone(), slot });
+
+        let welcome = ServerMessage::Welcome { slot, rules: self.rules.clone() };
+        for message in [welcome, snapshot] {
+            if let Err(err) = client.connection.send(&message) {
+                client.gone.get_or_insert(err.to_string());
+            }
+        }
+        self.send_players();
+    }
+
+    fn advance(&mut self, now: Instant) {
+        let has_players = self.clients.iter().any(|client| client.slot.is_some());
+
+        match self.phase {
+            Phase::Waiting if has_players => self.start_round(now),
+            Phase::Playing { next_step } if now >= next_step => {
+                self.step();
+
+                self.phase = if self.game.is_game_over() {
+                    Phase::Over { restart_at: now + ROUND_RESTART_DELAY }
+                } else {
+                    // Falling behind skips steps rather than rushing through them
+                    Phase::Playing { next_step: (next_step + self.game.tick_duration()).max(now) }
+                };
+            }
+            Phase::Over { restart_at } if now >= restart_at => {
+                if has_players {
+                    self.start_round(now);
+                } else {
+                    self.phase = Phase::Waiting;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn start_round(&mut self, now: Instant) {
+        // Every round gets a different mouse, while the first one plays the given seed
+        let seed = self.seed.wrapping_add(self.round as u64);
+        self.game = SnakeSim::new(self.rules.clone(), seed);
+        self.round += 1;
+        self.tick = 0;
+        for client in &mut self.clients {
+            client.queue.clear();
+        }
+        self.events.push(ServerEvent::RoundStarted { round: self.round, seed });
+
+        self.phase = Phase::Playing { next_step: now + self.game.tick_duration() };
+        self.send_all(&self.snapshot());
+    }
+
+    fn step(&mut self) {
+        let mut inputs: Vec<Option<Direction>> = vec![None; self.rules.snake_count()];
+
+        for (id, input) in inputs.iter_mut().enumerate() {
+            let snake = self.game.snake(id);
+            *input = match self.clients.iter_mut().find(|client| client.slot == Some(id)) {
+                Some(client) => std::iter::from_fn(|| client.queue.pop_front()).find(|&d| snake.can_turn(d)),
+                None => ai::choose_direction(&self.game, id, self.rules.rival_behaviour),
+            };
+        }
+
+        self.game.step(&inputs);
+        self.tick += 1;
+
+        if self.game.is_game_over() {
+            let scores = self.game.snakes()[..self.rules.players].iter().map(|snake| snake.score()).collect();
+            self.events.push(ServerEvent::RoundOver { round: self.round, ticks: self.tick, scores });
+        }
+
+        self.send_all(&self.snapshot());
+    }
+
+    fn snapshot(&self) -> ServerMessage {
+        ServerMessage::Snapshot { round: self.round, tick: self.tick, state: self.game.snapshot() }
+    }
+
+    fn send_players(&mut self) {
+        let mut players = vec![None; self.rules.players];
+        for client in &self.clients {
+            if let (Some(slot), None) = (client.slot, &client.gone) {
+                players[slot] = Some(client.name.clone());
+            }
+        }
+
+        self.send_all(&ServerMessage::Players(players));
+    }
+
+    fn send_all(&mut self, message: &ServerMessage) {
+        for client in self.clients.iter_mut().filter(|client| client.slot.is_some() && client.gone.is_none()) {
+            if let Err(err) = client.connection.send(message) {
+                client.gone = Some(err.to_string());
+            }
+        }
+    }
+
+    fn remove_gone(&mut self) {
+        // Telling the others about a player leaving can lose another one
+        while self.clients.iter().any(|client| client.gone.is_some()) {
+            let mut left = false;
+            let events = &mut self.events;
+
+            self.clients.retain(|client| match (&client.gone, client.slot) {
+                (None, _) => true,
+                (Some(reason), Some(slot)) => {
+                    events.push(ServerEvent::Left { name: client.name.clone(), slot, reason: reason.clone() });
+                    left = true;
+                    false
+                }
+                (Some(_), None) => false,
+            });
+
+            if left {
+                self.send_players();
+            }
+        }
+    }
+}
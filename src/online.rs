@@ -0,0 +1,194 @@
+use std::time::Instant;
+use bevy::prelude::*;
+use crate::bindings::{Action, Actions, Bindings};
+use crate::config::Config;
+use crate::gamepad::PadTurn;
+use crate::net::{ClientMessage, Connection, NetError, ServerMessage, PING_INTERVAL, PROTOCOL_VERSION, TIMEOUT};
+use crate::sim::{Direction, Rules, SnakeSim};
+use crate::snake::{update_blocks, update_scoreboard, Game, GameComponents, GameState, Scoreboard};
+
+const STATUS_FONT_SIZE: f32 = 18.0;
+const STATUS_PADDING: Val = Val::Px(10.0);
+
+// A game played on a server, the client only sends turns and shows what the server sends back
+#[derive(Resource)]
+pub struct OnlineSession {
+    connection: Connection,
+    slot: usize,
+    rules: Rules,
+    players: Vec<Option<String>>,
+    round: usize,
+    ping: Timer,
+    error: Option<String>,
+}
+
+impl OnlineSession {
+    pub fn join(address: &str, name: &str) -> Result<OnlineSession, NetError> {
+        let mut connection = Connection::connect(address)?;
+        connection.send(&ClientMessage::Join { version: PROTOCOL_VERSION, name: name.to_string() })?;
+
+        let (slot, rules) = match connection.wait(TIMEOUT)? {
+            ServerMessage::Welcome { slot, rules } => (slot, rules),
+            ServerMessage::Refused(reason) => return Err(NetError::Refused(reason)),
+            _ => return Err(NetError::Protocol(String::from("expected a welcome from the server"))),
+        };
+        rules.validate().map_err(NetError::Protocol)?;
+
+        Ok(OnlineSession {
+            connection,
+            slot,
+            players: vec![None; rules.players],
+            rules,
+            round: 0,
+            ping: Timer::new(PING_INTERVAL, TimerMode::Repeating),
+            error: None,
+        })
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        if let Err(err) = self.connection.send(message) {
+            self.error.get_or_insert(err.to_string());
+        }
+    }
+}
+
+pub struct OnlinePlugin;
+
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Online), setup_status)
+            .add_systems(Update, (
+                send_turns,
+                receive_updates,
+                update_blocks,
+                update_scoreboard,
+                update_status,
+            ).chain().run_if(in_state(GameState::Online)));
+    }
+}
+
+#[derive(Component)]
+struct OnlineStatus;
+
+fn setup_status(mut commands: Commands, config: Res<Config>) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle {
+            font_size: STATUS_FONT_SIZE,
+            color: config.colors.scoreboard.into(),
+            ..default()
+        }).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: STATUS_PADDING,
+            left: STATUS_PADDING,
+            ..default()
+        }),
+        OnlineStatus,
+        GameComponents,
+    ));
+}
+
+// The keys of both players steer as well as the first gamepad, the server decides whether a turn is possible
+fn send_turns(actions: Actions, mut pad_turns: EventReader<PadTurn>, mut session: ResMut<OnlineSession>) {
+    if session.error.is_some() {
+        return;
+    }
+
+    let directions: Vec<Direction> = actions.turns()
+        .into_iter()
+        .map(|(_, direction)| direction)
+        .chain(pad_turns.read().filter(|turn| turn.pad == 0).map(|turn| turn.direction))
+        .collect();
+
+    for direction in directions {
+        session.send(&ClientMessage::Turn(direction));
+    }
+}
+
+fn receive_updates(
+    time: Res<Time>,
+    mut session: ResMut<OnlineSession>,
+    mut game: ResMut<Game>,
+    mut scoreboard: ResMut<Scoreboard>,
+) {
+    if session.error.is_some() {
+        return;
+    }
+
+    if session.ping.tick(time.delta()).just_finished() {
+        session.send(&ClientMessage::Ping);
+    }
+
+    let now = Instant::now();
+    let messages = match session.connection.receive(now) {
+        Ok(messages) => messages,
+        Err(err) => {
+            session.error = Some(err.to_string());
+            return;
+        }
+    };
+
+    // Snapshots that arrived together are only shown up to the latest one
+    let mut latest = None;
+    for message in messages {
+        match message {
+            ServerMessage::Snapshot { round, state, .. } => {
+                session.round = round;
+                latest = Some(state);
+            }
+            ServerMessage::Players(players) => session.players = players,
+            ServerMessage::Refused(reason) => session.error = Some(NetError::Refused(reason).to_string()),
+            ServerMessage::Welcome { .. } => {}
+        }
+    }
+
+    if let Some(state) = latest {
+        game.0 = SnakeSim::from_snapshot(session.rules.clone(), state);
+        scoreboard.sync(&game);
+    }
+
+    // The server sends a snapshot on every tick, so silence means it is gone
+    if session.connection.is_timed_out(now) {
+        session.error = Some(NetError::TimedOut.to_string());
+    }
+}
+
+fn update_status(
+    session: Res<OnlineSession>,
+    game: Res<Game>,
+    bindings: Res<Bindings>,
+    mut query: Query<&mut Text, With<OnlineStatus>>,
+) {
+    if !session.is_changed() && !game.is_changed() {
+        return;
+    }
+
+    let players: Vec<String> = session.players.iter()
+        .enumerate()
+        .map(|(i, name)| format!(
+            "P{} {}{}",
+            i + 1,
+            name.as_deref().unwrap_or("COMPUTER"),
+            if i == session.slot { " (YOU)" } else { "" },
+        ))
+        .collect();
+
+    let state = if let Some(err) = &session.error {
+        format!("DISCONNECTED: {}", err.to_uppercase())
+    } else if game.is_game_over() {
+        match game.winner() {
+            Some(i) => format!("ROUND OVER, P{} WINS", i + 1),
+            None if session.players.len() > 1 => String::from("ROUND OVER, DRAW"),
+            None => String::from("ROUND OVER"),
+        }
+    } else {
+        format!("ROUND {}", session.round)
+    };
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("{}\n{}  {}: QUIT", players.join("  "), state, bindings.keys_text(Action::Quit));
+    }
+}
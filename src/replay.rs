@@ -0,0 +1,332 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::bindings::{Action, Actions, Bindings};
+use crate::config::Config;
+use crate::sim::{Direction, Rules, SnakeSim};
+use crate::snake::{update_blocks, update_scoreboard, Game, GameComponents, GameState, Scoreboard};
+
+pub const REPLAYS_PATH: &str = "replays";
+
+const SEEK_TICKS: usize = 50;
+const MAX_SPEED: u32 = 8;
+
+const STATUS_FONT_SIZE: f32 = 18.0;
+const STATUS_PADDING: Val = Val::Px(10.0);
+
+// Everything needed to re-run a game: the rules, the seed and every turn the snakes took.
+// Inputs that did not change a direction are left out, they have no effect on the game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub rules: Rules,
+    pub ticks: usize,
+    pub turns: Vec<Turn>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Turn {
+    pub tick: usize,
+    pub snake: usize,
+    pub direction: Direction,
+}
+
+impl Replay {
+    pub fn new(rules: Rules, seed: u64) -> Replay {
+        Replay { seed, rules, ticks: 0, turns: Vec::new() }
+    }
+
+    // Records the inputs of a step, has to be called before the step is made
+    pub fn record(&mut self, game: &SnakeSim, inputs: &[Option<Direction>]) {
+        for (snake, input) in inputs.iter().enumerate() {
+            if let Some(direction) = *input {
+                let state = game.snake(snake);
+                if state.is_alive() && state.can_turn(direction) && state.direction() != direction {
+                    self.turns.push(Turn { tick: self.ticks, snake, direction });
+                }
+            }
+        }
+
+        self.ticks += 1;
+    }
+
+    pub fn inputs(&self, tick: usize) -> Vec<Option<Direction>> {
+        let mut inputs = vec![None; self.rules.snake_count()];
+
+        let start = self.turns.partition_point(|turn| turn.tick < tick);
+        for turn in self.turns[start..].iter().take_while(|turn| turn.tick == tick) {
+            if let Some(input) = inputs.get_mut(turn.snake) {
+                *input = Some(turn.direction);
+            }
+        }
+
+        inputs
+    }
+
+    // The game as it was after the given number of ticks
+    pub fn play_to(&self, tick: usize) -> SnakeSim {
+        let mut game = SnakeSim::new(self.rules.clone(), self.seed);
+        for tick in 0..tick.min(self.ticks) {
+            game.step(&self.inputs(tick));
+        }
+
+        game
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let text = fs::read_to_string(path).map_err(|err| ReplayError::Read(path.to_path_buf(), err))?;
+        let replay: Replay = ron::from_str(&text).map_err(|err| ReplayError::Parse(path.to_path_buf(), err))?;
+
+        replay.rules.validate().map_err(ReplayError::Invalid)?;
+
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        // One line per turn, replays of long games get big otherwise
+        let pretty = ron::ser::PrettyConfig::default().depth_limit(2);
+        let text = ron::ser::to_string_pretty(self, pretty).map_err(ReplayError::Serialize)?;
+
+        path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text))
+            .map_err(|err| ReplayError::Write(path.to_path_buf(), err))
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Serialize(ron::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Read(path, err) => write!(f, "cannot read replay {}: {}", path.display(), err),
+            ReplayError::Write(path, err) => write!(f, "cannot write replay {}: {}", path.display(), err),
+            ReplayError::Parse(path, err) => write!(f, "invalid replay {}: {}", path.display(), err),
+            ReplayError::Serialize(err) => write!(f, "cannot serialize replay: {}", err),
+            ReplayError::Invalid(reason) => write!(f, "invalid replay rules: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+// The replay of the game being played, restarted together with the game
+#[derive(Resource, Deref, DerefMut)]
+pub struct Recording(pub Replay);
+
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+    paused: bool,
+    speed: u32,
+    timer: Timer,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            tick: 0,
+            paused: false,
+            speed: 1,
+            timer: Timer::new(Duration::ZERO, TimerMode::Repeating),
+        }
+    }
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, save_replay.run_if(in_state(GameState::GameOver)))
+            .add_systems(OnEnter(GameState::Replay), setup_playback)
+            .add_systems(Update, (
+                control_playback,
+                playback,
+                update_blocks,
+                update_scoreboard,
+                update_playback_status,
+            ).chain().run_if(in_state(GameState::Replay)));
+    }
+}
+
+#[derive(Component)]
+struct PlaybackStatus;
+
+#[derive(Component)]
+struct ReplaySaved;
+
+fn save_replay(
+    mut commands: Commands,
+    actions: Actions,
+    config: Res<Config>,
+    recording: Res<Recording>,
+    saved_query: Query<Entity, With<ReplaySaved>>,
+) {
+    if !actions.just_pressed(Action::SaveReplay) {
+        return;
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let path = Path::new(REPLAYS_PATH).join(format!("{}-{}.ron", timestamp, recording.seed));
+
+    let status = match recording.save(&path) {
+        Ok(()) => format!("REPLAY SAVED TO {}", path.display()),
+        Err(err) => err.to_string().to_uppercase(),
+    };
+
+    for entity in &saved_query {
+        commands.entity(entity).despawn();
+    }
+    commands.spawn((
+        TextBundle::from_section(status, TextStyle {
+            font_size: STATUS_FONT_SIZE,
+            color: config.colors.scoreboard.into(),
+            ..default()
+        }).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: STATUS_PADDING,
+            left: STATUS_PADDING,
+            ..default()
+        }),
+        ReplaySaved,
+        GameComponents,
+    ));
+}
+
+fn setup_playback(mut commands: Commands, config: Res<Config>) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle {
+            font_size: STATUS_FONT_SIZE,
+            color: config.colors.scoreboard.into(),
+            ..default()
+        }).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: STATUS_PADDING,
+            left: STATUS_PADDING,
+            ..default()
+        }),
+        PlaybackStatus,
+        GameComponents,
+    ));
+}
+
+fn control_playback(
+    actions: Actions,
+    mut playback: ResMut<Playback>,
+    mut game: ResMut<Game>,
+    mut scoreboard: ResMut<Scoreboard>,
+) {
+    if actions.just_pressed(Action::Pause) {
+        playback.paused = !playback.paused;
+    }
+    if actions.just_pressed(Action::PlaybackSpeed) {
+        playback.speed = if playback.speed >= MAX_SPEED { 1 } else { playback.speed * 2 };
+    }
+    if actions.just_pressed(Action::PlaybackStep) {
+        playback.paused = true;
+        advance(&mut playback, &mut game);
+    }
+
+    // The left and right turn keys of either player seek
+    let directions = actions.directions();
+    let target = if actions.just_pressed(Action::PlaybackRestart) {
+        Some(0)
+    } else if directions.contains(&Direction::Left) {
+        Some(playback.tick.saturating_sub(SEEK_TICKS))
+    } else if directions.contains(&Direction::Right) {
+        Some((playback.tick + SEEK_TICKS).min(playback.replay.ticks))
+    } else {
+        None
+    };
+
+    // Seeking re-runs the game from the start, the simulation is cheap enough for that
+    if let Some(target) = target {
+        game.0 = playback.replay.play_to(target);
+        playback.tick = target;
+    }
+
+    if game.is_changed() {
+        scoreboard.sync(&game);
+    }
+}
+
+fn playback(
+    time: Res<Time>,
+    mut playback: ResMut<Playback>,
+    mut game: ResMut<Game>,
+    mut scoreboard: ResMut<Scoreboard>,
+) {
+    if playback.paused {
+        return;
+    }
+
+    let duration = game.tick_duration();
+    let speed = playback.speed;
+    playback.timer.set_duration(duration);
+    playback.timer.tick(time.delta() * speed);
+
+    for _ in 0..playback.timer.times_finished_this_tick() {
+        advance(&mut playback, &mut game);
+        scoreboard.sync(&game);
+    }
+
+    // Stop at the end, so the final position stays on screen
+    if playback.tick >= playback.replay.ticks && !playback.paused {
+        playback.paused = true;
+    }
+}
+
+fn advance(playback: &mut Playback, game: &mut Game) {
+    if playback.tick >= playback.replay.ticks || game.is_game_over() {
+        return;
+    }
+
+    game.step(&playback.replay.inputs(playback.tick));
+    playback.tick += 1;
+}
+
+fn update_playback_status(
+    playback: Res<Playback>,
+    bindings: Res<Bindings>,
+    mut query: Query<&mut Text, With<PlaybackStatus>>,
+) {
+    if !playback.is_changed() {
+        return;
+    }
+
+    let state = if playback.tick >= playback.replay.ticks {
+        "END"
+    } else if playback.paused {
+        "PAUSED"
+    } else {
+        "PLAYING"
+    };
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "REPLAY {}/{}  x{}  {}\n{}: PAUSE  {}: STEP  {}: SPEED  {}/{}: SEEK  {}: RESTART",
+            playback.tick,
+            playback.replay.ticks,
+            playback.speed,
+            state,
+            bindings.keys_text(Action::Pause),
+            bindings.keys_text(Action::PlaybackStep),
+            bindings.keys_text(Action::PlaybackSpeed),
+            bindings.direction_text(Direction::Left),
+            bindings.direction_text(Direction::Right),
+            bindings.keys_text(Action::PlaybackRestart),
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::sim::{Board, GameMode, Rules};
+
+const HIGH_SCORES_DIR: &str = "snake";
+const HIGH_SCORES_FILE: &str = "high_scores.toml";
+const MAX_HIGH_SCORES: usize = 10;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// All tables in one list sorted by score, every game mode and board size keeps its own top scores
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighScores {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<HighScore>,
+}
+
+// Fields added after the first version default, so older tables still load
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HighScore {
+    #[serde(default)]
+    pub name: String,
+    pub score: usize,
+    #[serde(default)]
+    pub length: usize,
+    pub difficulty: usize,
+    #[serde(default)]
+    pub mode: GameMode,
+    #[serde(default)]
+    pub board: Board,
+    pub seed: u64,
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+impl HighScore {
+    pub fn table(&self) -> Table {
+        Table { mode: self.mode, board: self.board }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Table {
+    pub mode: GameMode,
+    pub board: Board,
+}
+
+impl From<&Rules> for Table {
+    fn from(rules: &Rules) -> Self {
+        Table { mode: rules.mode, board: rules.board }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}x{}", self.mode, self.board.width, self.board.height)
+    }
+}
+
+// Where the high scores live, nothing is saved when it is not set
+#[derive(Resource, Clone, Debug)]
+pub struct HighScoresPath(pub PathBuf);
+
+impl HighScores {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join(HIGH_SCORES_DIR).join(HIGH_SCORES_FILE))
+    }
+
+    // A missing file is just an empty table
+    pub fn load(path: &Path) -> Result<HighScores, ScoresError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HighScores::default()),
+            Err(err) => return Err(ScoresError::Read(path.to_path_buf(), err)),
+        };
+
+        toml::from_str(&text).map_err(|err| ScoresError::Parse(path.to_path_buf(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ScoresError> {
+        let text = toml::to_string(self).map_err(ScoresError::Serialize)?;
+
+        path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text))
+            .map_err(|err| ScoresError::Write(path.to_path_buf(), err))
+    }
+
+    pub fn table(&self, table: Table) -> impl Iterator<Item = &HighScore> {
+        self.entries.iter().filter(move |entry| entry.table() == table)
+    }
+
+    // Every table with at least one score, in the order of their best scores
+    pub fn tables(&self) -> Vec<Table> {
+        let mut tables: Vec<Table> = Vec::new();
+        for entry in &self.entries {
+            if !tables.contains(&entry.table()) {
+                tables.push(entry.table());
+            }
+        }
+
+        tables
+    }
+
+    pub fn best(&self, table: Table) -> usize {
+        self.table(table).next().map_or(0, |entry| entry.score)
+    }
+
+    // The rank a score would get, or None when it would not make it into the table.
+    // Ties go below the older scores.
+    pub fn rank(&self, table: Table, score: usize) -> Option<usize> {
+        if score == 0 {
+            return None;
+        }
+
+        let rank = self.table(table).filter(|entry| entry.score >= score).count();
+
+        (rank < MAX_HIGH_SCORES).then_some(rank)
+    }
+
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        let table = entry.table();
+        let rank = self.rank(table, entry.score)?;
+
+        let position = self.entries.partition_point(|other| other.score >= entry.score);
+        self.entries.insert(position, entry);
+
+        if self.table(table).count() > MAX_HIGH_SCORES {
+            if let Some(last) = self.entries.iter().rposition(|entry| entry.table() == table) {
+                self.entries.remove(last);
+            }
+        }
+
+        Some(rank)
+    }
+}
+
+// A unix timestamp as a YYYY-MM-DD date in UTC
+pub fn format_date(timestamp: u64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / SECONDS_PER_DAY) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[derive(Debug)]
+pub enum ScoresError {
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for ScoresError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScoresError::Read(path, err) => write!(f, "cannot read high scores {}: {}", path.display(), err),
+            ScoresError::Write(path, err) => write!(f, "cannot write high scores {}: {}", path.display(), err),
+            ScoresError::Parse(path, err) => write!(f, "invalid high scores {}: {}", path.display(), err),
+            ScoresError::Serialize(err) => write!(f, "cannot serialize high scores: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ScoresError {}
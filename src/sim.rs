@@ -0,0 +1,1598 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+const BOARD_WIDTH: i32 = 40;
+const BOARD_HEIGHT: i32 = 22;
+// Keeps the grid of a huge board in memory and its cell count inside an i32
+const MAX_BOARD_CELLS: i64 = 4_000_000;
+
+const SCORE_DELTA: usize = 100;
+
+const SNAKE_STARTING_LENGTH: i32 = 4;
+const SNAKE_STARTING_POSITION: Cell = Cell::new(0, 0);
+const SNAKE_STARTING_DIRECTION: Direction = Direction::Right;
+
+const TIMER_STARTING_DURATION: f32 = 0.16;
+const TIMER_SCALING_PERCENTAGE: f32 = 15.0;
+const TIMER_MIN_DURATION: f32 = 0.05;
+const SCORE_DIFFICULTY_THRESHOLD: usize = 500;
+
+const EASY_SPEED: SpeedSettings = SpeedSettings {
+    speed_curve: SpeedCurve::Linear,
+    timer_starting_duration: 0.2,
+    timer_scaling_percentage: 5.0,
+    timer_min_duration: 0.08,
+    score_difficulty_threshold: 800,
+    starting_difficulty: 0,
+};
+const NORMAL_SPEED: SpeedSettings = SpeedSettings {
+    speed_curve: SpeedCurve::Exponential,
+    timer_starting_duration: TIMER_STARTING_DURATION,
+    timer_scaling_percentage: TIMER_SCALING_PERCENTAGE,
+    timer_min_duration: TIMER_MIN_DURATION,
+    score_difficulty_threshold: SCORE_DIFFICULTY_THRESHOLD,
+    starting_difficulty: 0,
+};
+const HARD_SPEED: SpeedSettings = SpeedSettings {
+    speed_curve: SpeedCurve::Exponential,
+    timer_starting_duration: 0.13,
+    timer_scaling_percentage: 15.0,
+    timer_min_duration: 0.04,
+    score_difficulty_threshold: 400,
+    starting_difficulty: 2,
+};
+const INSANE_SPEED: SpeedSettings = SpeedSettings {
+    speed_curve: SpeedCurve::Exponential,
+    timer_starting_duration: 0.1,
+    timer_scaling_percentage: 20.0,
+    timer_min_duration: 0.03,
+    score_difficulty_threshold: 300,
+    starting_difficulty: 4,
+};
+
+const MAX_PLAYERS: usize = 4;
+const SPAWN_LANE_SPACING: i32 = 4;
+
+const MOUSE_SPAWN_ATTEMPTS: usize = 32;
+
+const GOLDEN_MOUSE_SCORE_MULTIPLIER: usize = 5;
+const POISON_SHRINK: usize = 3;
+const POISON_LIFETIME: usize = 50;
+const SPEED_EFFECT_TICKS: usize = 60;
+const SPEED_EFFECT_LEVELS: i32 = 2;
+
+const BONUS_CHANCE: u32 = 20;
+const BONUS_LIFETIME: usize = 40;
+const BONUS_SCORE: usize = 500;
+
+const POWER_UP_CHANCE: u32 = 10;
+const POWER_UP_DURATION: usize = 60;
+const POWER_UP_LIFETIME: usize = 60;
+const SLOW_MOTION_FACTOR: f32 = 1.6;
+const MAGNET_RANGE: i32 = 8;
+
+const COMBO_WINDOW: usize = 25;
+const MAX_COMBO: usize = 5;
+const DIFFICULTY_BONUS_PERCENT: usize = 10;
+const LENGTH_BONUS_PERCENT: usize = 2;
+
+const MOUSE_MOVE_INTERVAL: usize = 2;
+const MOUSE_FLEE_DISTANCE: i32 = 5;
+const MOUSE_STAMINA: usize = 150;
+
+const CLASSIC_FOOD_WEIGHTS: FoodWeights = FoodWeights { mouse: 100, golden_mouse: 10, poison: 10, speed_up: 6, slow_down: 6 };
+const WRAP_FOOD_WEIGHTS: FoodWeights = FoodWeights { mouse: 100, golden_mouse: 8, poison: 16, speed_up: 10, slow_down: 4 };
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Cell {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Cell {
+    pub const fn new(x: i32, y: i32) -> Cell {
+        Cell { x, y }
+    }
+
+    pub fn step(&self, direction: Direction) -> Cell {
+        let (dx, dy) = direction.offset();
+
+        Cell::new(self.x + dx, self.y + dy)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Left,
+    Right,
+    Down,
+    Up,
+}
+
+impl Direction {
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, -1),
+            Direction::Up => (0, 1),
+        }
+    }
+
+    pub fn reverse(&self) -> Direction {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Board {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Board {
+    pub fn half_width(&self) -> i32 {
+        self.width / 2
+    }
+
+    pub fn half_height(&self) -> i32 {
+        self.height / 2
+    }
+
+    pub fn is_inside(&self, cell: Cell) -> bool {
+        cell.x.abs() < self.half_width() && cell.y.abs() < self.half_height()
+    }
+
+    // Brings a cell that left the playable area back in from the opposite side
+    pub fn wrap(&self, cell: Cell) -> Cell {
+        let x_pos = self.half_width() - 1;
+        let y_pos = self.half_height() - 1;
+
+        Cell::new(
+            (cell.x + x_pos).rem_euclid(2 * x_pos + 1) - x_pos,
+            (cell.y + y_pos).rem_euclid(2 * y_pos + 1) - y_pos,
+        )
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board { width: BOARD_WIDTH, height: BOARD_HEIGHT }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    #[default]
+    Classic,
+    Wrap,
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(GameMode::Classic),
+            "wrap" => Ok(GameMode::Wrap),
+            _ => Err(format!("unknown game mode `{}`, expected `classic` or `wrap`", s)),
+        }
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameMode::Classic => write!(f, "classic"),
+            GameMode::Wrap => write!(f, "wrap"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RivalBehaviour {
+    // Heads straight for the mouse
+    #[default]
+    Greedy,
+    // Goes for the mouse, but keeps away from other heads and from dead ends
+    Cautious,
+    // Cuts off the closest snake unless it can get to the mouse first
+    Aggressive,
+}
+
+impl FromStr for RivalBehaviour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "greedy" => Ok(RivalBehaviour::Greedy),
+            "cautious" => Ok(RivalBehaviour::Cautious),
+            "aggressive" => Ok(RivalBehaviour::Aggressive),
+            _ => Err(format!("unknown rival behaviour `{}`, expected `greedy`, `cautious` or `aggressive`", s)),
+        }
+    }
+}
+
+impl fmt::Display for RivalBehaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RivalBehaviour::Greedy => write!(f, "greedy"),
+            RivalBehaviour::Cautious => write!(f, "cautious"),
+            RivalBehaviour::Aggressive => write!(f, "aggressive"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedCurve {
+    // Every level takes the scaling percentage off the tick of the level before
+    #[default]
+    Exponential,
+    // Every level takes the scaling percentage of the starting tick off
+    Linear,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DifficultyPreset {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+pub const DIFFICULTY_PRESETS: [DifficultyPreset; 4] =
+    [DifficultyPreset::Easy, DifficultyPreset::Normal, DifficultyPreset::Hard, DifficultyPreset::Insane];
+
+impl DifficultyPreset {
+    // The preset the speed of the rules was taken from, None when it was tuned by hand
+    pub fn of(rules: &Rules) -> Option<DifficultyPreset> {
+        DIFFICULTY_PRESETS.iter().copied().find(|preset| preset.speed() == rules.speed())
+    }
+
+    pub fn apply(self, rules: &mut Rules) {
+        let speed = self.speed();
+        rules.speed_curve = speed.speed_curve;
+        rules.timer_starting_duration = speed.timer_starting_duration;
+        rules.timer_scaling_percentage = speed.timer_scaling_percentage;
+        rules.timer_min_duration = speed.timer_min_duration;
+        rules.score_difficulty_threshold = speed.score_difficulty_threshold;
+        rules.starting_difficulty = speed.starting_difficulty;
+    }
+
+    pub fn next(self) -> DifficultyPreset {
+        let i = DIFFICULTY_PRESETS.iter().position(|&preset| preset == self).unwrap_or(0);
+
+        DIFFICULTY_PRESETS[(i + 1) % DIFFICULTY_PRESETS.len()]
+    }
+
+    fn speed(self) -> SpeedSettings {
+        match self {
+            DifficultyPreset::Easy => EASY_SPEED,
+            DifficultyPreset::Normal => NORMAL_SPEED,
+            DifficultyPreset::Hard => HARD_SPEED,
+            DifficultyPreset::Insane => INSANE_SPEED,
+        }
+    }
+}
+
+impl FromStr for DifficultyPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(DifficultyPreset::Easy),
+            "normal" => Ok(DifficultyPreset::Normal),
+            "hard" => Ok(DifficultyPreset::Hard),
+            "insane" => Ok(DifficultyPreset::Insane),
+            _ => Err(format!("unknown difficulty preset `{}`, expected `easy`, `normal`, `hard` or `insane`", s)),
+        }
+    }
+}
+
+impl fmt::Display for DifficultyPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DifficultyPreset::Easy => write!(f, "easy"),
+            DifficultyPreset::Normal => write!(f, "normal"),
+            DifficultyPreset::Hard => write!(f, "hard"),
+            DifficultyPreset::Insane => write!(f, "insane"),
+        }
+    }
+}
+
+// The part of the rules a difficulty preset decides
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct SpeedSettings {
+    speed_curve: SpeedCurve,
+    timer_starting_duration: f32,
+    timer_scaling_percentage: f32,
+    timer_min_duration: f32,
+    score_difficulty_threshold: usize,
+    starting_difficulty: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Food {
+    #[default]
+    Mouse,
+    // Worth several mice
+    GoldenMouse,
+    // Takes segments away instead of adding one, never below the head
+    Poison,
+    // Make the game faster or slower for a while
+    SpeedUp,
+    SlowDown,
+}
+
+impl Food {
+    // Only mice run around, the other foods stay where they show up
+    pub fn is_mouse(self) -> bool {
+        matches!(self, Food::Mouse | Food::GoldenMouse)
+    }
+}
+
+pub const FOODS: [Food; 5] = [Food::Mouse, Food::GoldenMouse, Food::Poison, Food::SpeedUp, Food::SlowDown];
+
+// How likely each food is to show up, relative to the others
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FoodWeights {
+    #[serde(default)]
+    pub mouse: u32,
+    #[serde(default)]
+    pub golden_mouse: u32,
+    #[serde(default)]
+    pub poison: u32,
+    #[serde(default)]
+    pub speed_up: u32,
+    #[serde(default)]
+    pub slow_down: u32,
+}
+
+impl FoodWeights {
+    pub fn weight(&self, food: Food) -> u32 {
+        match food {
+            Food::Mouse => self.mouse,
+            Food::GoldenMouse => self.golden_mouse,
+            Food::Poison => self.poison,
+            Food::SpeedUp => self.speed_up,
+            Food::SlowDown => self.slow_down,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        FOODS.iter().map(|&food| self.weight(food)).sum()
+    }
+}
+
+// The food weights of every game mode, the rules only use the ones of their mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoodRules {
+    pub classic: FoodWeights,
+    pub wrap: FoodWeights,
+}
+
+impl Default for FoodRules {
+    fn default() -> Self {
+        FoodRules { classic: CLASSIC_FOOD_WEIGHTS, wrap: WRAP_FOOD_WEIGHTS }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerUp {
+    // Goes through its own body
+    Ghost,
+    // Makes the whole game slower
+    SlowMotion,
+    // Pulls the mouse towards the head
+    Magnet,
+    // Bounces off the next wall instead of crashing into it
+    Shield,
+}
+
+pub const POWER_UPS: [PowerUp; 4] = [PowerUp::Ghost, PowerUp::SlowMotion, PowerUp::Magnet, PowerUp::Shield];
+
+impl fmt::Display for PowerUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerUp::Ghost => write!(f, "ghost"),
+            PowerUp::SlowMotion => write!(f, "slow motion"),
+            PowerUp::Magnet => write!(f, "magnet"),
+            PowerUp::Shield => write!(f, "shield"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MouseBehaviour {
+    // Sits where it showed up
+    #[default]
+    Still,
+    // Runs around at random, and away from heads that come close
+    Wandering,
+    // Sits still until a head comes close, then runs away
+    Skittish,
+    // Always keeps as far away from the heads as it can
+    Sly,
+}
+
+impl FromStr for MouseBehaviour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "still" => Ok(MouseBehaviour::Still),
+            "wandering" => Ok(MouseBehaviour::Wandering),
+            "skittish" => Ok(MouseBehaviour::Skittish),
+            "sly" => Ok(MouseBehaviour::Sly),
+            _ => Err(format!("unknown mouse behaviour `{}`, expected `still`, `wandering`, `skittish` or `sly`", s)),
+        }
+    }
+}
+
+impl fmt::Display for MouseBehaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MouseBehaviour::Still => write!(f, "still"),
+            MouseBehaviour::Wandering => write!(f, "wandering"),
+            MouseBehaviour::Skittish => write!(f, "skittish"),
+            MouseBehaviour::Sly => write!(f, "sly"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spawn {
+    pub position: Cell,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    pub mode: GameMode,
+    pub board: Board,
+    pub players: usize,
+    pub rivals: usize,
+    pub rival_behaviour: RivalBehaviour,
+    pub mouse_behaviour: MouseBehaviour,
+    // Mice take a step every so many ticks, pellets never move
+    pub mouse_move_interval: usize,
+    pub score_delta: usize,
+    // Food eaten within the window of the last one counts up a combo, which multiplies the score up to the max
+    pub combo_window: usize,
+    pub max_combo: usize,
+    // Extra score in percent for every difficulty level and for every segment grown
+    pub difficulty_bonus_percent: usize,
+    pub length_bonus_percent: usize,
+    pub snake_starting_length: i32,
+    pub snake_starting_position: Cell,
+    pub snake_starting_direction: Direction,
+    pub timer_starting_duration: f32,
+    pub speed_curve: SpeedCurve,
+    pub timer_scaling_percentage: f32,
+    // Ticks never get any shorter, however high the difficulty
+    pub timer_min_duration: f32,
+    pub score_difficulty_threshold: usize,
+    pub starting_difficulty: usize,
+    pub food: FoodRules,
+    // Percentage of eaten mice that bring out a bonus for a while
+    pub bonus_chance: u32,
+    pub bonus_lifetime: usize,
+    pub bonus_score: usize,
+    // Percentage of eaten mice that bring out a power-up, which lasts for the duration once picked up
+    pub power_up_chance: u32,
+    pub power_up_duration: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub walls: Vec<Cell>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mouse_spots: Vec<Cell>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra_spawns: Vec<Spawn>,
+}
+
+impl Rules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.board.width < 4 || self.board.height < 4 {
+            return Err(format!(
+                "board must be at least 4x4, got {}x{}",
+                self.board.width, self.board.height,
+            ));
+        }
+        // The board size includes the border, odd sizes would only get rounded down to the next even one
+        if self.board.width % 2 != 0 || self.board.height % 2 != 0 {
+            return Err(format!(
+                "board width and height must be even, got {}x{}",
+                self.board.width, self.board.height,
+            ));
+        }
+        if self.board.width as i64 * self.board.height as i64 > MAX_BOARD_CELLS {
+            return Err(format!(
+                "board must have at most {} cells, got {}x{}",
+                MAX_BOARD_CELLS, self.board.width, self.board.height,
+            ));
+        }
+        if self.players == 0 || self.players > MAX_PLAYERS {
+            return Err(format!("players must be between 1 and {}, got {}", MAX_PLAYERS, self.players));
+        }
+        if self.snake_starting_length < 1 {
+            return Err(String::from("snake_starting_length must be at least 1"));
+        }
+
+        if let Some(wall) = self.walls.iter().find(|&&wall| !self.board.is_inside(wall)) {
+            return Err(format!("wall at ({}, {}) is outside of the board", wall.x, wall.y));
+        }
+
+        let mut taken: Vec<Cell> = Vec::new();
+        for i in 0..self.snake_count() {
+            let spawn = self.snake_spawn(i);
+            let cells: Vec<Cell> = self.snake_starting_cells(i).collect();
+
+            if cells.iter().any(|&cell| !self.board.is_inside(cell)) {
+                return Err(format!(
+                    "snake {} of length {} starting at ({}, {}) does not fit into a {}x{} board",
+                    i + 1,
+                    self.snake_starting_length,
+                    spawn.position.x,
+                    spawn.position.y,
+                    self.board.width,
+                    self.board.height,
+                ));
+            }
+            if let Some(cell) = cells.iter().find(|&cell| self.walls.contains(cell)) {
+                return Err(format!("starting snake {} runs into a wall at ({}, {})", i + 1, cell.x, cell.y));
+            }
+            if let Some(cell) = cells.iter().find(|&cell| taken.contains(cell)) {
+                return Err(format!("starting snake {} overlaps another snake at ({}, {})", i + 1, cell.x, cell.y));
+            }
+
+            taken.extend(cells);
+        }
+
+        if let Some(spot) = self.mouse_spots.iter().find(|&spot| !self.board.is_inside(*spot) || self.walls.contains(spot)) {
+            return Err(format!("mouse spot at ({}, {}) is not a free cell", spot.x, spot.y));
+        }
+
+        let free_cells = (2 * self.board.half_width() - 1) * (2 * self.board.half_height() - 1) - self.walls.len() as i32;
+        if taken.len() as i32 >= free_cells {
+            return Err(String::from("the starting snakes leave no room for a mouse"));
+        }
+        if !self.timer_starting_duration.is_finite() || self.timer_starting_duration <= 0.0 {
+            return Err(String::from("timer_starting_duration must be positive"));
+        }
+        if !(0.0..100.0).contains(&self.timer_scaling_percentage) {
+            return Err(String::from("timer_scaling_percentage must be between 0 and 100"));
+        }
+        if !self.timer_min_duration.is_finite() || self.timer_min_duration <= 0.0 {
+            return Err(String::from("timer_min_duration must be positive"));
+        }
+        if self.score_difficulty_threshold == 0 {
+            return Err(String::from("score_difficulty_threshold must be positive"));
+        }
+        if self.max_combo == 0 {
+            return Err(String::from("max_combo must be at least 1"));
+        }
+        if self.mouse_move_interval == 0 {
+            return Err(String::from("mouse_move_interval must be at least 1"));
+        }
+        if self.bonus_chance > 100 {
+            return Err(String::from("bonus_chance must be between 0 and 100"));
+        }
+        if self.bonus_lifetime == 0 {
+            return Err(String::from("bonus_lifetime must be at least 1"));
+        }
+        if self.power_up_chance > 100 {
+            return Err(String::from("power_up_chance must be between 0 and 100"));
+        }
+        if self.power_up_duration == 0 {
+            return Err(String::from("power_up_duration must be at least 1"));
+        }
+        for (mode, weights) in [(GameMode::Classic, self.food.classic), (GameMode::Wrap, self.food.wrap)] {
+            if weights.total() == 0 {
+                return Err(format!("food weights of the {} mode must not all be zero", mode));
+            }
+        }
+
+        Ok(())
+    }
+
+    // How long a tick lasts at the level, never shorter than the minimum
+    pub fn tick_seconds(&self, level: i32) -> f32 {
+        let scale = self.timer_scaling_percentage / 100.0;
+        let seconds = match self.speed_curve {
+            SpeedCurve::Exponential => self.timer_starting_duration * (1.0 - scale).powi(level),
+            SpeedCurve::Linear => self.timer_starting_duration * (1.0 - scale * level as f32),
+        };
+
+        seconds.max(self.timer_min_duration)
+    }
+
+    fn speed(&self) -> SpeedSettings {
+        SpeedSettings {
+            speed_curve: self.speed_curve,
+            timer_starting_duration: self.timer_starting_duration,
+            timer_scaling_percentage: self.timer_scaling_percentage,
+            timer_min_duration: self.timer_min_duration,
+            score_difficulty_threshold: self.score_difficulty_threshold,
+            starting_difficulty: self.starting_difficulty,
+        }
+    }
+
+    pub fn food_weights(&self) -> &FoodWeights {
+        match self.mode {
+            GameMode::Classic => &self.food.classic,
+            GameMode::Wrap => &self.food.wrap,
+        }
+    }
+
+    // The quicker a bonus is reached, the more it is worth
+    pub fn bonus_points(&self, ticks_left: usize) -> usize {
+        (self.bonus_score * ticks_left).div_ceil(self.bonus_lifetime)
+    }
+
+    // Players come first, computer controlled rivals after them
+    pub fn snake_count(&self) -> usize {
+        self.players + self.rivals
+    }
+
+    pub fn is_rival(&self, i: usize) -> bool {
+        i >= self.players
+    }
+
+    pub fn snake_spawn(&self, i: usize) -> Spawn {
+        let first = Spawn { position: self.snake_starting_position, direction: self.snake_starting_direction };
+        if i == 0 {
+            return first;
+        }
+        if let Some(&spawn) = self.extra_spawns.get(i - 1) {
+            return spawn;
+        }
+
+        // Without a spawn from the level, snakes take parallel lanes on alternating sides of the first one,
+        // every other snake mirrored so that they head towards each other
+        let mirrored = !i.is_multiple_of(2);
+        let lane = (i as i32 + 1) / 2 * SPAWN_LANE_SPACING;
+        let lane = if mirrored { -lane } else { lane };
+        let Cell { x, y } = first.position;
+
+        let position = match first.direction {
+            Direction::Left | Direction::Right if mirrored => Cell::new(-x, y + lane),
+            Direction::Left | Direction::Right => Cell::new(x, y + lane),
+            Direction::Up | Direction::Down if mirrored => Cell::new(x + lane, -y),
+            Direction::Up | Direction::Down => Cell::new(x + lane, y),
+        };
+        let direction = if mirrored { first.direction.reverse() } else { first.direction };
+
+        Spawn { position, direction }
+    }
+
+    pub fn snake_starting_cells(&self, i: usize) -> impl Iterator<Item = Cell> {
+        let spawn = self.snake_spawn(i);
+        let start = spawn.position;
+        let (dx, dy) = spawn.direction.reverse().offset();
+
+        (0..self.snake_starting_length).map(move |i| Cell::new(start.x + i * dx, start.y + i * dy))
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            mode: GameMode::default(),
+            board: Board::default(),
+            players: 1,
+            rivals: 0,
+            rival_behaviour: RivalBehaviour::default(),
+            mouse_behaviour: MouseBehaviour::default(),
+            mouse_move_interval: MOUSE_MOVE_INTERVAL,
+            score_delta: SCORE_DELTA,
+            combo_window: COMBO_WINDOW,
+            max_combo: MAX_COMBO,
+            difficulty_bonus_percent: DIFFICULTY_BONUS_PERCENT,
+            length_bonus_percent: LENGTH_BONUS_PERCENT,
+            snake_starting_length: SNAKE_STARTING_LENGTH,
+            snake_starting_position: SNAKE_STARTING_POSITION,
+            snake_starting_direction: SNAKE_STARTING_DIRECTION,
+            timer_starting_duration: TIMER_STARTING_DURATION,
+            speed_curve: SpeedCurve::default(),
+            timer_scaling_percentage: TIMER_SCALING_PERCENTAGE,
+            timer_min_duration: TIMER_MIN_DURATION,
+            score_difficulty_threshold: SCORE_DIFFICULTY_THRESHOLD,
+            starting_difficulty: 0,
+            food: FoodRules::default(),
+            bonus_chance: BONUS_CHANCE,
+            bonus_lifetime: BONUS_LIFETIME,
+            bonus_score: BONUS_SCORE,
+            power_up_chance: POWER_UP_CHANCE,
+            power_up_duration: POWER_UP_DURATION,
+            walls: Vec::new(),
+            mouse_spots: Vec::new(),
+            extra_spawns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Snake,
+    Mouse,
+    Bonus,
+    PowerUp,
+}
+
+pub struct Grid {
+    half_width: i32,
+    half_height: i32,
+    tiles: Vec<Tile>,
+}
+
+impl Grid {
+    pub fn new(board: Board) -> Grid {
+        let half_width = board.half_width();
+        let half_height = board.half_height();
+        let tiles = (2 * half_width as usize + 1).checked_mul(2 * half_height as usize + 1)
+            .expect("the board is too large for a grid");
+        let mut grid = Grid {
+            half_width,
+            half_height,
+            tiles: vec![Tile::Empty; tiles],
+        };
+
+        for x in -half_width..=half_width {
+            grid.set(Cell::new(x, -half_height), Tile::Wall);
+            grid.set(Cell::new(x, half_height), Tile::Wall);
+        }
+        for y in -half_height..=half_height {
+            grid.set(Cell::new(-half_width, y), Tile::Wall);
+            grid.set(Cell::new(half_width, y), Tile::Wall);
+        }
+
+        grid
+    }
+
+    pub fn half_width(&self) -> i32 {
+        self.half_width
+    }
+
+    pub fn half_height(&self) -> i32 {
+        self.half_height
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        (-self.half_height..=self.half_height)
+            .flat_map(move |y| (-self.half_width..=self.half_width).map(move |x| Cell::new(x, y)))
+    }
+
+    // Anything outside of the grid is treated as a wall
+    pub fn get(&self, cell: Cell) -> Tile {
+        self.index(cell).map_or(Tile::Wall, |i| self.tiles[i])
+    }
+
+    pub fn set(&mut self, cell: Cell, tile: Tile) {
+        if let Some(i) = self.index(cell) {
+            self.tiles[i] = tile;
+        }
+    }
+
+    fn index(&self, cell: Cell) -> Option<usize> {
+        if cell.x.abs() > self.half_width || cell.y.abs() > self.half_height {
+            return None;
+        }
+
+        let row = (cell.y + self.half_height) * (2 * self.half_width + 1);
+
+        Some((row + cell.x + self.half_width) as usize)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepOutcome {
+    Moved,
+    // The food and the points it was worth
+    Ate(Food, usize),
+    // Ate the food and made the game harder
+    DifficultyUp(Food, usize),
+    AteBonus(usize),
+    PickedUp(PowerUp),
+    // The shield took the hit of a wall
+    Shielded,
+    Died,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnakeState {
+    body: VecDeque<Cell>,
+    direction: Direction,
+    score: usize,
+    alive: bool,
+    #[serde(default)]
+    effects: Vec<Effect>,
+    #[serde(default)]
+    combo: usize,
+    #[serde(default)]
+    combo_ticks: usize,
+}
+
+impl SnakeState {
+    pub fn body(&self) -> &VecDeque<Cell> {
+        &self.body
+    }
+
+    pub fn head(&self) -> Cell {
+        self.body[0]
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    pub fn can_turn(&self, direction: Direction) -> bool {
+        direction.reverse() != self.direction
+    }
+
+    // The power-ups that are still active
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    pub fn has_effect(&self, power_up: PowerUp) -> bool {
+        self.effects.iter().any(|effect| effect.power_up == power_up)
+    }
+
+    // Food eaten in quick succession, 0 once the window to eat the next one has passed
+    pub fn combo(&self) -> usize {
+        self.combo
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Effect {
+    pub power_up: PowerUp,
+    pub ticks_left: usize,
+}
+
+// A power-up waiting on the board to be picked up
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PowerUpItem {
+    pub cell: Cell,
+    pub power_up: PowerUp,
+    pub ticks_left: usize,
+}
+
+// Food that only stays for a while
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Bonus {
+    pub cell: Cell,
+    pub ticks_left: usize,
+}
+
+// Everything about a game that changes while playing, enough to show it somewhere else
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub seed: u64,
+    pub snakes: Vec<SnakeState>,
+    pub mouse: Cell,
+    #[serde(default)]
+    pub food: Food,
+    #[serde(default)]
+    pub bonus: Option<Bonus>,
+    #[serde(default)]
+    pub power_up: Option<PowerUpItem>,
+    pub difficulty: usize,
+    pub game_over: bool,
+}
+
+pub struct SnakeSim {
+    rules: Rules,
+    seed: u64,
+    rng: ChaCha8Rng,
+    grid: Grid,
+    snakes: Vec<SnakeState>,
+    mouse: Cell,
+    food: Food,
+    // Ticks since the food showed up
+    food_age: usize,
+    // Difficulty levels added by speed food, until the effect runs out
+    speed: i32,
+    speed_ticks: usize,
+    bonus: Option<Bonus>,
+    bonus_expired: bool,
+    power_up: Option<PowerUpItem>,
+    // The points of the players before any multipliers, which is what drives the difficulty
+    progress: usize,
+    difficulty: usize,
+    game_over: bool,
+}
+
+impl SnakeSim {
+    pub fn new(rules: Rules, seed: u64) -> SnakeSim {
+        let mut grid = Grid::new(rules.board);
+        for &cell in &rules.walls {
+            grid.set(cell, Tile::Wall);
+        }
+
+        let snakes: Vec<SnakeState> = (0..rules.snake_count()).map(|i| SnakeState {
+            body: rules.snake_starting_cells(i).collect(),
+            direction: rules.snake_spawn(i).direction,
+            score: 0,
+            alive: true,
+            effects: Vec::new(),
+            combo: 0,
+            combo_ticks: 0,
+        }).collect();
+        for &cell in snakes.iter().flat_map(|snake| &snake.body) {
+            grid.set(cell, Tile::Snake);
+        }
+
+        let mut sim = SnakeSim {
+            difficulty: rules.starting_difficulty,
+            mouse: rules.snake_starting_position,
+            food: Food::Mouse,
+            food_age: 0,
+            speed: 0,
+            speed_ticks: 0,
+            bonus: None,
+            bonus_expired: false,
+            power_up: None,
+            progress: 0,
+            rules,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            grid,
+            snakes,
+            game_over: false,
+        };
+        sim.spawn_food();
+
+        sim
+    }
+
+    // A game in the state of the snapshot. The random numbers start over from the seed, so the game
+    // is only good for showing, stepping it does not continue the original game.
+    pub fn from_snapshot(rules: Rules, snapshot: Snapshot) -> SnakeSim {
+        let mut grid = Grid::new(rules.board);
+        for &cell in &rules.walls {
+            grid.set(cell, Tile::Wall);
+        }
+        // The heads of dead snakes are where they crashed, those cells keep what they were
+        for snake in &snapshot.snakes {
+            for &cell in snake.body.iter().skip(if snake.alive { 0 } else { 1 }) {
+                grid.set(cell, Tile::Snake);
+            }
+        }
+        grid.set(snapshot.mouse, Tile::Mouse);
+        if let Some(bonus) = snapshot.bonus {
+            grid.set(bonus.cell, Tile::Bonus);
+        }
+        if let Some(item) = snapshot.power_up {
+            grid.set(item.cell, Tile::PowerUp);
+        }
+
+        SnakeSim {
+            rules,
+            seed: snapshot.seed,
+            rng: ChaCha8Rng::seed_from_u64(snapshot.seed),
+            grid,
+            snakes: snapshot.snakes,
+            mouse: snapshot.mouse,
+            food: snapshot.food,
+            food_age: 0,
+            speed: 0,
+            speed_ticks: 0,
+            bonus: snapshot.bonus,
+            bonus_expired: false,
+            power_up: snapshot.power_up,
+            progress: 0,
+            difficulty: snapshot.difficulty,
+            game_over: snapshot.game_over,
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            seed: self.seed,
+            snakes: self.snakes.clone(),
+            mouse: self.mouse,
+            food: self.food,
+            bonus: self.bonus,
+            power_up: self.power_up,
+            difficulty: self.difficulty,
+            game_over: self.game_over,
+        }
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn snakes(&self) -> &[SnakeState] {
+        &self.snakes
+    }
+
+    pub fn snake(&self, i: usize) -> &SnakeState {
+        &self.snakes[i]
+    }
+
+    // What the snake's combo multiplies its points by at the moment, it stops growing at the max combo
+    pub fn combo_multiplier(&self, i: usize) -> usize {
+        self.snakes[i].combo.min(self.rules.max_combo)
+    }
+
+    pub fn mouse(&self) -> Cell {
+        self.mouse
+    }
+
+    // What the mouse cell holds
+    pub fn food(&self) -> Food {
+        self.food
+    }
+
+    pub fn bonus(&self) -> Option<Bonus> {
+        self.bonus
+    }
+
+    pub fn power_up(&self) -> Option<PowerUpItem> {
+        self.power_up
+    }
+
+    // Whether the bonus ran out on the last step without anyone eating it
+    pub fn bonus_expired(&self) -> bool {
+        self.bonus_expired
+    }
+
+    // The combined score of the players
+    pub fn score(&self) -> usize {
+        self.snakes[..self.rules.players].iter().map(|snake| snake.score).sum()
+    }
+
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    // The last player standing, or the one with the best score when nobody survived
+    pub fn winner(&self) -> Option<usize> {
+        let players = &self.snakes[..self.rules.players];
+        if !self.game_over || players.len() < 2 {
+            return None;
+        }
+
+        let alive: Vec<usize> = (0..players.len()).filter(|&i| players[i].alive).collect();
+        let candidates = if alive.is_empty() { (0..players.len()).collect() } else { alive };
+
+        let best = candidates.iter().map(|&i| players[i].score).max()?;
+        let mut leaders = candidates.into_iter().filter(|&i| players[i].score == best);
+
+        match (leaders.next(), leaders.next()) {
+            (Some(i), None) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        let slow_motion = self.snakes.iter().any(|snake| snake.has_effect(PowerUp::SlowMotion));
+        let factor = if slow_motion { SLOW_MOTION_FACTOR } else { 1.0 };
+
+        Duration::from_secs_f32(self.rules.tick_seconds(self.difficulty as i32 + self.speed) * factor)
+    }
+
+    // Steps needed to get from one cell to another on an empty board
+    pub fn distance(&self, from: Cell, to: Cell) -> i32 {
+        let (dx, dy) = self.offset(from, to);
+
+        dx.abs() + dy.abs()
+    }
+
+    // The shortest steps from one cell to another, across the edges in wrap mode
+    fn offset(&self, from: Cell, to: Cell) -> (i32, i32) {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+
+        match self.rules.mode {
+            GameMode::Classic => (dx, dy),
+            GameMode::Wrap => {
+                let width = 2 * self.grid.half_width() - 1;
+                let height = 2 * self.grid.half_height() - 1;
+
+                ((dx + width / 2).rem_euclid(width) - width / 2, (dy + height / 2).rem_euclid(height) - height / 2)
+            }
+        }
+    }
+
+    // The cell next to the given one, taking the board topology into account
+    pub fn neighbour(&self, cell: Cell, direction: Direction) -> Cell {
+        let next = cell.step(direction);
+
+        match self.rules.mode {
+            GameMode::Classic => next,
+            GameMode::Wrap => self.rules.board.wrap(next),
+        }
+    }
+
+    // Moves every living snake by one cell, inputs are indexed by snake and missing ones keep the direction.
+    // Returns the outcome for each snake that was still alive before the step.
+    pub fn step(&mut self, inputs: &[Option<Direction>]) -> Vec<(usize, StepOutcome)> {
+        if self.game_over {
+            return Vec::new();
+        }
+
+        if self.speed_ticks > 0 {
+            self.speed_ticks -= 1;
+            if self.speed_ticks == 0 {
+                self.speed = 0;
+            }
+        }
+        self.food_age += 1;
+        self.bonus_expired = false;
+
+        let alive: Vec<usize> = (0..self.snakes.len()).filter(|&i| self.snakes[i].alive).collect();
+
+        for &i in &alive {
+            if let Some(&Some(direction)) = inputs.get(i) {
+                if self.snakes[i].can_turn(direction) {
+                    self.snakes[i].direction = direction;
+                }
+            }
+
+            for effect in &mut self.snakes[i].effects {
+                effect.ticks_left -= 1;
+            }
+            self.snakes[i].effects.retain(|effect| effect.ticks_left > 0);
+
+            let snake = &mut self.snakes[i];
+            snake.combo_ticks = snake.combo_ticks.saturating_sub(1);
+            if snake.combo_ticks == 0 {
+                snake.combo = 0;
+            }
+        }
+
+        let shielded: Vec<bool> = alive.iter().map(|&i| self.use_shield(i)).collect();
+        // A shield with no free side to turn to keeps the snake where it is for this tick
+        let held: Vec<bool> = alive.iter().zip(&shielded).map(|(&i, &shielded)| {
+            let snake = &self.snakes[i];
+            shielded && self.grid.get(self.neighbour(snake.head(), snake.direction)) == Tile::Wall
+        }).collect();
+
+        let heads: Vec<Cell> = alive.iter().zip(&held)
+            .map(|(&i, &held)| {
+                let head = self.snakes[i].head();
+                if held { head } else { self.neighbour(head, self.snakes[i].direction) }
+            })
+            .collect();
+        let eats: Vec<bool> = heads.iter().map(|&head| self.grid.get(head) == Tile::Mouse).collect();
+        let eats_bonus: Vec<bool> = heads.iter().map(|&head| self.grid.get(head) == Tile::Bonus).collect();
+        let picks: Vec<bool> = heads.iter().map(|&head| self.grid.get(head) == Tile::PowerUp).collect();
+
+        // Tails leave their cells before heads enter new ones, except for the snakes that are about to grow
+        for (k, &i) in alive.iter().enumerate() {
+            if !held[k] && (!eats[k] || self.food == Food::Poison) {
+                let tail = self.snakes[i].body.pop_back().unwrap();
+                // A ghost may still have another segment on the cell
+                if !self.snakes[i].body.contains(&tail) {
+                    self.grid.set(tail, Tile::Empty);
+                }
+            }
+        }
+
+        // Running into a wall or any snake body kills, two heads meeting on the same cell kill both.
+        // Ghosts only die on the bodies of others.
+        let died: Vec<bool> = alive.iter().zip(&heads).zip(&held).map(|((&i, &head), &held)| {
+            if held {
+                return false;
+            }
+
+            let tile = self.grid.get(head);
+            let ghosting = self.snakes[i].has_effect(PowerUp::Ghost) && self.snakes.iter()
+                .enumerate()
+                .all(|(other, snake)| (other == i) == snake.body.contains(&head));
+
+            tile == Tile::Wall || (tile == Tile::Snake && !ghosting) || heads.iter().filter(|&&other| other == head).count() > 1
+        }).collect();
+
+        let mut outcomes = Vec::with_capacity(alive.len());
+        let mut eaten = None;
+        for (k, &i) in alive.iter().enumerate() {
+            if held[k] {
+                outcomes.push((i, StepOutcome::Shielded));
+                continue;
+            }
+
+            self.snakes[i].body.push_front(heads[k]);
+
+            if died[k] {
+                self.snakes[i].alive = false;
+                self.snakes[i].effects.clear();
+                outcomes.push((i, StepOutcome::Died));
+                continue;
+            }
+
+            self.grid.set(heads[k], Tile::Snake);
+
+            if eats[k] {
+                let points = self.eat(i);
+                eaten = Some((outcomes.len(), self.food, points));
+                outcomes.push((i, StepOutcome::Ate(self.food, points)));
+            } else if eats_bonus[k] {
+                let points = self.bonus.take().map_or(0, |bonus| self.rules.bonus_points(bonus.ticks_left));
+                self.snakes[i].score += points;
+                if !self.rules.is_rival(i) {
+                    self.progress += points;
+                }
+                outcomes.push((i, StepOutcome::AteBonus(points)));
+            } else if picks[k] {
+                let power_up = self.pick_up(i);
+                outcomes.push((i, StepOutcome::PickedUp(power_up)));
+            } else if shielded[k] {
+                outcomes.push((i, StepOutcome::Shielded));
+            } else {
+                outcomes.push((i, StepOutcome::Moved));
+            }
+        }
+
+        if let Some(bonus) = &mut self.bonus {
+            bonus.ticks_left -= 1;
+            if bonus.ticks_left == 0 {
+                self.grid.set(bonus.cell, Tile::Empty);
+                self.bonus = None;
+                self.bonus_expired = true;
+            }
+        }
+        if let Some(item) = &mut self.power_up {
+            item.ticks_left -= 1;
+            if item.ticks_left == 0 {
+                self.grid.set(item.cell, Tile::Empty);
+                self.power_up = None;
+            }
+        }
+
+        if let Some((k, _, _)) = eaten {
+            // The snakes fill the whole board, there is nowhere left to go
+            if !self.spawn_food() {
+                self.end_game();
+                outcomes[k].1 = StepOutcome::Died;
+                return outcomes;
+            }
+
+            self.spawn_bonus();
+            self.spawn_power_up();
+        } else if self.food == Food::Poison && self.food_age >= POISON_LIFETIME {
+            // Poison nobody wants goes away, otherwise nothing else would ever show up
+            self.grid.set(self.mouse, Tile::Empty);
+            if !self.spawn_food() {
+                self.grid.set(self.mouse, Tile::Mouse);
+            }
+        }
+
+        self.move_mouse();
+        self.pull_mouse();
+
+        let difficulty = self.rules.starting_difficulty + self.progress / self.rules.score_difficulty_threshold;
+        if difficulty != self.difficulty {
+            self.difficulty = difficulty;
+            if let Some((k, food, points)) = eaten {
+                outcomes[k].1 = StepOutcome::DifficultyUp(food, points);
+            }
+        }
+
+        // A single player plays until death, with more players the round ends when only one is left
+        let players_alive = self.snakes[..self.rules.players].iter().filter(|snake| snake.alive).count();
+        if players_alive == 0 || (self.rules.players > 1 && players_alive < 2) {
+            self.end_game();
+            return outcomes;
+        }
+
+        // Snakes that died while the game goes on are taken off the board
+        for &(i, outcome) in &outcomes {
+            if outcome == StepOutcome::Died {
+                let body = std::mem::take(&mut self.snakes[i].body);
+                for &cell in body.iter().skip(1) {
+                    self.grid.set(cell, Tile::Empty);
+                }
+            }
+        }
+
+        outcomes
+    }
+
+    // Nothing carries over past the end of a game
+    fn end_game(&mut self) {
+        self.game_over = true;
+        self.speed = 0;
+        self.speed_ticks = 0;
+        for snake in &mut self.snakes {
+            snake.effects.clear();
+        }
+    }
+
+    // A shielded snake about to hit a wall loses the shield and turns to a free side instead, or stops for a tick
+    // when there is none
+    fn use_shield(&mut self, i: usize) -> bool {
+        let snake = &self.snakes[i];
+        if !snake.has_effect(PowerUp::Shield) || self.grid.get(self.neighbour(snake.head(), snake.direction)) != Tile::Wall {
+            return false;
+        }
+
+        let head = snake.head();
+        let sides = match snake.direction {
+            Direction::Left | Direction::Right => [Direction::Up, Direction::Down],
+            Direction::Up | Direction::Down => [Direction::Left, Direction::Right],
+        };
+        let side = sides.iter().copied().find(|&side| {
+            matches!(self.grid.get(self.neighbour(head, side)), Tile::Empty | Tile::Mouse | Tile::Bonus | Tile::PowerUp)
+        });
+
+        let snake = &mut self.snakes[i];
+        snake.effects.retain(|effect| effect.power_up != PowerUp::Shield);
+        if let Some(side) = side {
+            snake.direction = side;
+        }
+
+        true
+    }
+
+    fn pick_up(&mut self, i: usize) -> PowerUp {
+        let item = self.power_up.take().unwrap();
+        let effects = &mut self.snakes[i].effects;
+        effects.retain(|effect| effect.power_up != item.power_up);
+        effects.push(Effect { power_up: item.power_up, ticks_left: self.rules.power_up_duration });
+
+        item.power_up
+    }
+
+    // Mice with a mind of their own take a step on their tick, never into anything but an empty cell.
+    // They tire out after a while, so that a mouse hiding in a corner cannot hold up the game forever.
+    fn move_mouse(&mut self) {
+        let behaviour = self.rules.mouse_behaviour;
+        let tired = self.food_age > MOUSE_STAMINA;
+        if behaviour == MouseBehaviour::Still || !self.food.is_mouse() || tired || !self.food_age.is_multiple_of(self.rules.mouse_move_interval) {
+            return;
+        }
+
+        let mouse = self.mouse;
+        let moves: Vec<Cell> = [Direction::Up, Direction::Right, Direction::Down, Direction::Left].iter()
+            .map(|&direction| self.neighbour(mouse, direction))
+            .filter(|&cell| self.grid.get(cell) == Tile::Empty)
+            .collect();
+        if moves.is_empty() {
+            return;
+        }
+
+        let fleeing = self.safety(mouse) <= MOUSE_FLEE_DISTANCE;
+        let next = match behaviour {
+            MouseBehaviour::Skittish if !fleeing => return,
+            MouseBehaviour::Wandering if !fleeing => moves[self.rng.gen_range(0..moves.len())],
+            _ => {
+                // The move that gets furthest from the closest head, staying put when nothing is better
+                let (cell, safety) = moves.iter()
+                    .map(|&cell| (cell, self.safety(cell)))
+                    .max_by_key(|&(_, safety)| safety)
+                    .unwrap();
+                if safety <= self.safety(mouse) {
+                    return;
+                }
+                cell
+            }
+        };
+
+        self.grid.set(mouse, Tile::Empty);
+        self.grid.set(next, Tile::Mouse);
+        self.mouse = next;
+    }
+
+    // How far the cell is from the closest living head
+    fn safety(&self, cell: Cell) -> i32 {
+        self.snakes.iter()
+            .filter(|snake| snake.alive)
+            .map(|snake| self.distance(snake.head(), cell))
+            .min()
+            .unwrap_or(i32::MAX)
+    }
+
+    // The mouse steps towards the closest head with a magnet in range, as long as the way is free
+    fn pull_mouse(&mut self) {
+        if !self.food.is_mouse() {
+            return;
+        }
+
+        let mouse = self.mouse;
+        let head = self.snakes.iter()
+            .filter(|snake| snake.alive && snake.has_effect(PowerUp::Magnet))
+            .map(|snake| snake.head())
+            .filter(|&head| self.distance(mouse, head) <= MAGNET_RANGE)
+            .min_by_key(|&head| self.distance(mouse, head));
+        let Some(head) = head else {
+            return;
+        };
+
+        let (dx, dy) = self.offset(mouse, head);
+        let direction = if dx.abs() >= dy.abs() {
+            if dx > 0 { Direction::Right } else { Direction::Left }
+        } else if dy > 0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        };
+
+        let next = self.neighbour(mouse, direction);
+        if self.grid.get(next) == Tile::Empty {
+            self.grid.set(mouse, Tile::Empty);
+            self.grid.set(next, Tile::Mouse);
+            self.mouse = next;
+        }
+    }
+
+    // The effect of the food on the snake that ate it, returns the points it scored
+    fn eat(&mut self, i: usize) -> usize {
+        let points = match self.food {
+            Food::Mouse | Food::SpeedUp | Food::SlowDown => self.points(i, self.rules.score_delta),
+            Food::GoldenMouse => self.points(i, self.rules.score_delta * GOLDEN_MOUSE_SCORE_MULTIPLIER),
+            Food::Poison => 0,
+        };
+
+        let snake = &mut self.snakes[i];
+        snake.score += points;
+
+        match self.food {
+            Food::Mouse | Food::GoldenMouse => {}
+            Food::Poison => {
+                // Poison breaks the combo as well
+                snake.combo = 0;
+                snake.combo_ticks = 0;
+
+                for _ in 0..POISON_SHRINK {
+                    if snake.body.len() <= 1 {
+                        break;
+                    }
+                    let tail = snake.body.pop_back().unwrap();
+                    if !snake.body.contains(&tail) {
+                        self.grid.set(tail, Tile::Empty);
+                    }
+                }
+            }
+            Food::SpeedUp | Food::SlowDown => {
+                self.speed = if self.food == Food::SpeedUp { SPEED_EFFECT_LEVELS } else { -SPEED_EFFECT_LEVELS };
+                self.speed_ticks = SPEED_EFFECT_TICKS;
+            }
+        }
+
+        points
+    }
+
+    // Counts the meal into the combo and scales the points by it, the difficulty and the length the snake had before
+    fn points(&mut self, i: usize, base: usize) -> usize {
+        if !self.rules.is_rival(i) {
+            self.progress += base;
+        }
+
+        let snake = &mut self.snakes[i];
+        snake.combo = if snake.combo_ticks > 0 { snake.combo + 1 } else { 1 };
+        snake.combo_ticks = self.rules.combo_window;
+
+        let grown = (snake.body.len() as i32 - 1 - self.rules.snake_starting_length).max(0) as usize;
+        let percent = 100 + self.difficulty * self.rules.difficulty_bonus_percent + grown * self.rules.length_bonus_percent;
+
+        base * self.combo_multiplier(i) * percent / 100
+    }
+
+    // Puts a random food on a free cell, false when there is no free cell left
+    fn spawn_food(&mut self) -> bool {
+        let Some(cell) = self.random_free_cell() else {
+            return false;
+        };
+
+        self.mouse = cell;
+        self.food = self.random_food();
+        self.food_age = 0;
+        self.grid.set(cell, Tile::Mouse);
+
+        true
+    }
+
+    // Now and then a bonus shows up on a random free cell, there is only ever one of them
+    fn spawn_bonus(&mut self) {
+        if self.bonus.is_some() || self.rng.gen_range(0..100) >= self.rules.bonus_chance {
+            return;
+        }
+
+        if let Some(cell) = self.random_free_cell() {
+            self.bonus = Some(Bonus { cell, ticks_left: self.rules.bonus_lifetime });
+            self.grid.set(cell, Tile::Bonus);
+        }
+    }
+
+    fn spawn_power_up(&mut self) {
+        if self.power_up.is_some() || self.rng.gen_range(0..100) >= self.rules.power_up_chance {
+            return;
+        }
+
+        let power_up = POWER_UPS[self.rng.gen_range(0..POWER_UPS.len())];
+        if let Some(cell) = self.random_free_cell() {
+            self.power_up = Some(PowerUpItem { cell, power_up, ticks_left: POWER_UP_LIFETIME });
+            self.grid.set(cell, Tile::PowerUp);
+        }
+    }
+
+    fn random_food(&mut self) -> Food {
+        let weights = *self.rules.food_weights();
+        let mut roll = self.rng.gen_range(0..weights.total());
+
+        for food in FOODS {
+            if roll < weights.weight(food) {
+                return food;
+            }
+            roll -= weights.weight(food);
+        }
+
+        Food::Mouse
+    }
+
+    fn random_free_cell(&mut self) -> Option<Cell> {
+        // Levels may restrict mice to a few spots, fall back to anywhere when all of them are taken
+        let spots: Vec<Cell> = self.rules.mouse_spots.iter()
+            .copied()
+            .filter(|&cell| self.grid.get(cell) == Tile::Empty)
+            .collect();
+        if !spots.is_empty() {
+            return Some(spots[self.rng.gen_range(0..spots.len())]);
+        }
+
+        let x_pos = self.grid.half_width() - 1;
+        let y_pos = self.grid.half_height() - 1;
+
+        for _ in 0..MOUSE_SPAWN_ATTEMPTS {
+            let cell = Cell::new(self.rng.gen_range(-x_pos..=x_pos), self.rng.gen_range(-y_pos..=y_pos));
+
+            if self.grid.get(cell) == Tile::Empty {
+                return Some(cell);
+            }
+        }
+
+        // The board is crowded, pick from the cells that are actually free
+        let free: Vec<Cell> = self.grid.cells().filter(|&cell| self.grid.get(cell) == Tile::Empty).collect();
+        if free.is_empty() {
+            return None;
+        }
+
+        Some(free[self.rng.gen_range(0..free.len())])
+    }
+}
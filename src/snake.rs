@@ -1,163 +1,306 @@
 use std::collections::{HashMap, VecDeque};
 use std::ops::Mul;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use bevy::{
+    app::AppExit,
+    ecs::system::SystemParam,
     prelude::*,
-    sprite::collide_aabb::collide,
     text::Text2dBounds,
+    window::PrimaryWindow,
 };
-use rand::Rng;
+use crate::ai;
+use crate::bindings::{Action, Actions, Bindings, BindingsPath, BindingsPlugin};
+use crate::config::Config;
+use crate::editor::EditorPlugin;
+use crate::gamepad::{self, GamepadPlugin, PadTurn};
+use crate::leaderboard::{EnteredName, LeaderboardPlugin};
+use crate::level::{LevelAsset, LevelLoader};
+use crate::online::OnlinePlugin;
+use crate::replay::{Recording, Replay, ReplayPlugin};
+use crate::scores::{HighScore, HighScores, HighScoresPath, Table};
+use crate::sim::{Cell, DifficultyPreset, Direction, Food, GameMode, PowerUp, SnakeSim, StepOutcome};
 
-const BLOCK_SIZE: Vec3 = Vec3::new(20.0, 20.0, 1.0);
-const SCREEN_HEIGHT: f32 = 22.0;
-const SCREEN_WIDTH: f32 = 40.0;
-
-const SCORE_DELTA: usize = 100;
 const SCOREBOARD_FONT_SIZE: f32 = 21.0;
 const SCOREBOARD_PADDING: Val = Val::Px(10.0);
 
-const MESSAGE_BOX_SIZE: Vec2 = Vec2::new(450.0, 200.0);
+const MESSAGE_BOX_SIZE: Vec2 = Vec2::new(640.0, 560.0);
 const MESSAGE_BOX_FONT_SIZE: f32 = 30.0;
-
-const SNAKE_STARTING_LENGTH: i32 = 4;
-const SNAKE_STARTING_POSITION: Position = Position::new(0.0, 0.0);
-const SNAKE_STARTING_DIRECTION: Direction = Direction::Right;
-
-const TIMER_STARTING_DURATION: f32 = 0.16;
-const TIMER_SCALING_PERCENTAGE: f32 = 15.0;
-const SCORE_DIFFICULTY_THRESHOLD: f32 = 500.0;
-
-const WALL_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
-const MOUSE_COLOR: Color = Color::rgb(1.0, 0.65, 0.34);
-const SNAKE_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
-const SCOREBOARD_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
-const MESSAGE_BOX_BACKGROUND_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
-const MESSAGE_BOX_TEXT_COLOR: Color = Color::rgb(0.0, 0.0, 0.0);
-const BACKGROUND_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
-
-const MAX_INPUT_QUEUE_LENGTH: usize = 2;
+const MESSAGE_HIGH_SCORES: usize = 3;
+
+// Points float up from where they were scored and fade out, the rise is in blocks per second
+const POPUP_FONT_SIZE: f32 = 18.0;
+const POPUP_DURATION: Duration = Duration::from_millis(800);
+const POPUP_RISE: f32 = 2.0;
+
+// Every food has a sound of its own, made from the few sound files there are by playing them faster or slower
+const FOOD_SOUNDS: [(Food, &str, f32); 5] = [
+    (Food::Mouse, "sounds/grow.mp3", 1.0),
+    (Food::GoldenMouse, "sounds/grow.mp3", 1.5),
+    (Food::Poison, "sounds/grow.mp3", 0.6),
+    (Food::SpeedUp, "sounds/difficulty_up.mp3", 1.6),
+    (Food::SlowDown, "sounds/difficulty_up.mp3", 0.7),
+];
+
+// The bonus shrinks down to this size while it runs out, it and power-ups blink on their last ticks
+const BONUS_MIN_SCALE: f32 = 0.4;
+const BLINK_TICKS: usize = 10;
 
 pub struct SnakeApp;
 
 impl Plugin for SnakeApp {
     fn build(&self, app: &mut App) {
-        app.insert_resource(ClearColor(BACKGROUND_COLOR))
-            .insert_resource(MoveTimer(Timer::from_seconds(TIMER_STARTING_DURATION, TimerMode::Repeating)))
-            .insert_resource(Scoreboard { score: 0, difficulty: 0 })
+        app.init_resource::<Config>();
+
+        let config = app.world.resource::<Config>().clone();
+        let seed = app.world.get_resource::<Seed>().map_or_else(rand::random, |seed| seed.0);
+        let recording = Replay::new(config.rules.clone(), seed);
+
+        // High scores are only kept on disk when there is a place for them, a broken file starts a fresh table
+        let high_scores = app.world.get_resource::<HighScoresPath>().map_or_else(HighScores::default, |path| {
+            HighScores::load(&path.0).unwrap_or_else(|err| {
+                warn!("{}", err);
+                HighScores::default()
+            })
+        });
+        let bindings = app.world.get_resource::<BindingsPath>().map_or_else(Bindings::default, |path| {
+            Bindings::load(&path.0).unwrap_or_else(|err| {
+                warn!("{}", err);
+                Bindings::default()
+            })
+        });
+        let game = SnakeSim::new(config.rules, seed);
+
+        app.insert_resource(ClearColor(config.colors.background.into()))
+            .insert_resource(MoveTimer(Timer::new(game.tick_duration(), TimerMode::Repeating)))
+            .insert_resource(Scoreboard::new(&game))
+            .insert_resource(Game(game))
+            .insert_resource(Recording(recording))
+            .insert_resource(high_scores)
+            .insert_resource(bindings)
+            .init_resource::<HighScoreRank>()
+            .insert_resource(Autopilot(config.input.autopilot))
+            .init_resource::<DirectionQueues>()
             .add_state::<GameState>()
             .add_event::<SoundEvent>()
+            .add_event::<StepEvent>()
+            .add_event::<PresetSelected>()
             .add_systems(Startup, (setup_once, setup))
-            .add_systems(Update, (handle_state_input, play_sounds))
+            .add_systems(Update, (handle_state_input, toggle_autopilot, quit, play_sounds, fit_camera))
             .add_systems(Update, (
-                update_scoreboard,
-                update_difficulty,
+                queue_input,
+                autopilot,
                 move_snake,
                 check_collisions,
-            ).run_if(in_state(GameState::Running)))
+                spawn_popups,
+                float_popups,
+                update_difficulty,
+                update_blocks,
+                update_scoreboard,
+            ).chain().run_if(in_state(GameState::Running)))
             .add_systems(OnEnter(GameState::Startup), spawn_message::<StartupMessage>)
             .add_systems(OnExit(GameState::Startup), despawn::<StartupMessage>)
+            .add_systems(Update, (
+                select_preset,
+                (
+                    despawn::<GameComponents>,
+                    despawn::<StartupMessage>,
+                    reset,
+                    setup,
+                    spawn_message::<StartupMessage>,
+                ).chain().run_if(on_event::<PresetSelected>()),
+            ).chain().run_if(in_state(GameState::Startup)))
             .add_systems(OnEnter(GameState::Paused), spawn_message::<PausedMessage>)
             .add_systems(OnExit(GameState::Paused), despawn::<PausedMessage>)
-            .add_systems(OnEnter(GameState::GameOver), (spawn_message::<GameOverMessage>, game_over))
+            .add_systems(OnEnter(GameState::GameOver), (
+                (record_high_score, spawn_message::<GameOverMessage>).chain(),
+                game_over,
+            ))
             .add_systems(OnExit(GameState::GameOver), (
                 despawn::<GameOverMessage>,
                 despawn::<GameComponents>,
-                reset,
-                setup,
+                (reset, setup).chain(),
             ))
+            .add_systems(OnEnter(GameState::Editor), despawn::<GameComponents>)
+            .add_systems(OnExit(GameState::Editor), (despawn::<GameComponents>, reset, setup).chain())
+            .add_plugins((EditorPlugin, GamepadPlugin, ReplayPlugin, LeaderboardPlugin, OnlinePlugin, BindingsPlugin))
         ;
+
+        // Levels are assets, so they can only be loaded when there is an asset server around
+        if app.is_plugin_added::<AssetPlugin>() {
+            app.init_asset::<LevelAsset>()
+                .init_asset_loader::<LevelLoader>()
+                .init_resource::<PendingLevel>()
+                .add_event::<LevelApplied>()
+                .add_systems(Update, (
+                    queue_level.run_if(level_loaded),
+                    (
+                        apply_level,
+                        (despawn::<GameComponents>, reset, setup).chain().run_if(on_event::<LevelApplied>()),
+                    ).chain().run_if(in_state(GameState::Startup)),
+                ).chain())
+                // Any other time the level waits for the next game, so it never replaces the board of a live one
+                .add_systems(OnExit(GameState::GameOver), apply_level.before(reset));
+        }
     }
 }
 
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
-enum GameState {
+pub enum GameState {
     #[default]
     Startup,
     Running,
     Paused,
     GameOver,
+    Editor,
+    Replay,
+    NameEntry,
+    Leaderboard,
+    Online,
+    Bindings,
 }
 
 #[derive(Component)]
-struct GameComponents;
+pub(crate) struct GameComponents;
 
 #[derive(Resource, Deref, DerefMut)]
 struct MoveTimer(Timer);
 
+#[derive(Resource, Deref, DerefMut)]
+pub struct Game(pub SnakeSim);
+
+#[derive(Resource, Clone, Copy)]
+pub struct Seed(pub u64);
+
+#[derive(Resource, Clone, Copy)]
+pub struct Autopilot(pub bool);
+
+// Where the last game ended up in the high scores
+#[derive(Resource, Default)]
+struct HighScoreRank(Option<usize>);
+
+// Directions waiting to be used on the next moves, one queue per player
+#[derive(Resource, Default, Deref, DerefMut)]
+struct DirectionQueues(Vec<VecDeque<Direction>>);
+
+#[derive(Resource)]
+struct LevelHandle(Handle<LevelAsset>);
+
+// Set when the level was loaded or its file changed, until the level gets applied
+#[derive(Resource, Default)]
+struct PendingLevel(bool);
+
+#[derive(Event)]
+struct LevelApplied;
+
+#[derive(Event)]
+struct StepEvent(usize, StepOutcome);
+
+#[derive(Event)]
+struct PresetSelected;
+
 #[derive(Component)]
-struct Snake(u32);
+pub(crate) struct Snake {
+    id: usize,
+    segment: usize,
+}
 
 #[derive(Bundle)]
 struct SnakeBundle {
     block_bundle: BlockBundle,
     snake: Snake,
-    direction: Direction,
-    collider: Collider,
     game_component: GameComponents,
 }
 
 impl SnakeBundle {
-    fn new(id: u32, block_bundle: BlockBundle, direction: Direction) -> SnakeBundle {
+    fn new(id: usize, segment: usize, block_bundle: BlockBundle) -> SnakeBundle {
         SnakeBundle {
             block_bundle,
-            snake: Snake(id),
-            direction,
-            collider: Collider,
+            snake: Snake { id, segment },
             game_component: GameComponents,
         }
     }
 }
 
 #[derive(Component)]
-struct Mouse;
+pub(crate) struct Mouse;
 
 #[derive(Bundle)]
 struct MouseBundle {
     block_bundle: BlockBundle,
     mouse: Mouse,
-    collider: Collider,
     game_component: GameComponents,
 }
 
 impl MouseBundle {
-    fn new(block_size: Vec3) -> MouseBundle {
-        let x_pos = SCREEN_WIDTH / 2.0 - 1.0;
-        let y_pos = SCREEN_HEIGHT / 2.0 - 1.0;
-
-        let mut rng = rand::thread_rng();
-
+    fn new(color: Color, position: Position, block_size: Vec3) -> MouseBundle {
         MouseBundle {
-            block_bundle: BlockBundle::new(
-                MOUSE_COLOR,
-                Position(Vec2::new(
-                    rng.gen_range(-x_pos..=x_pos).round(),
-                    rng.gen_range(-y_pos..=y_pos).round(),
-                )),
-                block_size,
-            ),
+            block_bundle: BlockBundle::new(color, position, block_size),
             mouse: Mouse,
-            collider: Collider,
             game_component: GameComponents,
         }
     }
 }
 
+#[derive(Component)]
+pub(crate) struct Bonus;
+
+#[derive(Bundle)]
+struct BonusBundle {
+    block_bundle: BlockBundle,
+    bonus: Bonus,
+    game_component: GameComponents,
+}
+
+impl BonusBundle {
+    // Hidden until there is a bonus to show
+    fn new(color: Color, block_size: Vec3) -> BonusBundle {
+        let mut block_bundle = BlockBundle::new(color, Cell::new(0, 0).into(), block_size);
+        block_bundle.sprite_bundle.visibility = Visibility::Hidden;
+
+        BonusBundle {
+            block_bundle,
+            bonus: Bonus,
+            game_component: GameComponents,
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct PowerUpBlock;
+
 #[derive(Bundle)]
-struct BlockBundle {
+struct PowerUpBundle {
+    block_bundle: BlockBundle,
+    power_up: PowerUpBlock,
+    game_component: GameComponents,
+}
+
+impl PowerUpBundle {
+    // Hidden until there is a power-up to show
+    fn new(block_size: Vec3) -> PowerUpBundle {
+        let mut block_bundle = BlockBundle::new(Color::NONE, Cell::new(0, 0).into(), block_size);
+        block_bundle.sprite_bundle.visibility = Visibility::Hidden;
+
+        PowerUpBundle {
+            block_bundle,
+            power_up: PowerUpBlock,
+            game_component: GameComponents,
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub(crate) struct BlockBundle {
     sprite_bundle: SpriteBundle,
     position: Position,
 }
 
 impl BlockBundle {
-    fn new(color: Color, position: Position, block_size: Vec3) -> BlockBundle {
+    pub(crate) fn new(color: Color, position: Position, block_size: Vec3) -> BlockBundle {
         BlockBundle {
             sprite_bundle: SpriteBundle {
                 transform: Transform {
-                    translation: Vec3::new(
-                        position.x * block_size.x,
-                        position.y * block_size.y,
-                        0.0,
-                    ),
+                    translation: position.translation(block_size),
                     scale: block_size,
                     ..default()
                 },
@@ -172,144 +315,176 @@ impl BlockBundle {
     }
 }
 
-#[derive(Component, Clone)]
-struct Id(i32);
-
 #[derive(Component, Deref, DerefMut)]
-struct Position(Vec2);
-
-impl Position {
-    const fn new(x: f32, y: f32) -> Position {
-        Position(Vec2::new(x, y))
-    }
+pub(crate) struct Position(IVec2);
 
-    fn apply_vel(&mut self, velocity: &Velocity) {
-        self.x += velocity.x;
-        self.y += velocity.y;
+impl From<Cell> for Position {
+    fn from(cell: Cell) -> Self {
+        Position(IVec2::new(cell.x, cell.y))
     }
+}
 
-    fn translation(&self) -> Vec3 {
+impl Position {
+    fn translation(&self, block_size: Vec3) -> Vec3 {
         Vec3::new(
-            self.x * BLOCK_SIZE.x,
-            self.y * BLOCK_SIZE.y,
+            self.x as f32 * block_size.x,
+            self.y as f32 * block_size.y,
             0.0,
         )
     }
 }
 
-#[derive(Component, Copy, Clone, PartialEq, Debug)]
-enum Direction {
-    Left,
-    Right,
-    Down,
-    Up,
-}
-
-impl Direction {
-    fn velocity(&self) -> Velocity {
-        match self {
-            Direction::Left => Velocity(Vec2::new(-1.0, 0.0)),
-            Direction::Right => Velocity(Vec2::new(1.0, 0.0)),
-            Direction::Down => Velocity(Vec2::new(0.0, -1.0)),
-            Direction::Up => Velocity(Vec2::new(0.0, 1.0)),
-        }
-    }
-
-    fn reverse(&self) -> Direction {
-        match self {
-            Direction::Left => Direction::Right,
-            Direction::Right => Direction::Left,
-            Direction::Down => Direction::Up,
-            Direction::Up => Direction::Down,
-        }
-    }
-}
-
-#[derive(Component, Deref, DerefMut)]
-struct Velocity(Vec2);
-
 #[derive(Bundle)]
-struct WallBundle {
+pub(crate) struct WallBundle {
     sprite_bundle: SpriteBundle,
-    collider: Collider,
     game_component: GameComponents,
 }
 
 impl WallBundle {
-    fn new(location: WallLocation, block_size: Vec3) -> WallBundle {
+    pub(crate) fn new(location: WallLocation, config: &Config) -> WallBundle {
         WallBundle {
             sprite_bundle: SpriteBundle {
                 transform: Transform {
-                    translation: location.translation(block_size),
-                    scale: location.scale(block_size),
+                    translation: location.translation(config),
+                    scale: location.scale(config),
                     ..default()
                 },
                 sprite: Sprite {
-                    color: WALL_COLOR,
+                    color: config.colors.wall.into(),
                     ..default()
                 },
                 ..default()
             },
-            collider: Collider,
             game_component: GameComponents,
         }
     }
 }
 
-#[derive(Component)]
-struct Collider;
-
-enum WallLocation {
+pub(crate) enum WallLocation {
     Left,
     Right,
     Bottom,
     Top,
+    Block(Cell),
 }
 
 impl WallLocation {
-    fn translation(&self, block_size: Vec3) -> Vec3 {
-        let (start, end) = self.points();
+    fn translation(&self, config: &Config) -> Vec3 {
+        let (start, end) = self.points(config);
 
         let x_pos = (start.x + end.x) / 2.0;
         let y_pos = (start.y + end.y) / 2.0;
 
-        Vec3::new(x_pos, y_pos, 0.0).mul(block_size)
+        Vec3::new(x_pos, y_pos, 0.0).mul(config.block_scale())
     }
 
-    fn scale(&self, block_size: Vec3) -> Vec3 {
-        let (start, end) = self.points();
+    fn scale(&self, config: &Config) -> Vec3 {
+        let (start, end) = self.points(config);
 
         let dx = (start.x - end.x).abs() + 1.0;
         let dy = (start.y - end.y).abs() + 1.0;
 
-        Vec3::new(dx, dy, 1.0).mul(block_size)
+        Vec3::new(dx, dy, 1.0).mul(config.block_scale())
     }
 
-    fn points(&self) -> (Vec2, Vec2) {
-        let x_pos = SCREEN_WIDTH / 2.0;
-        let y_pos = SCREEN_HEIGHT / 2.0;
+    fn points(&self, config: &Config) -> (Vec2, Vec2) {
+        let x_pos = config.rules.board.half_width() as f32;
+        let y_pos = config.rules.board.half_height() as f32;
 
         match self {
             WallLocation::Left => (Vec2::new(-x_pos, -y_pos), Vec2::new(-x_pos, y_pos)),
             WallLocation::Right => (Vec2::new(x_pos, y_pos), Vec2::new(x_pos, -y_pos)),
             WallLocation::Bottom => (Vec2::new(x_pos, -y_pos), Vec2::new(-x_pos, -y_pos)),
             WallLocation::Top => (Vec2::new(-x_pos, y_pos), Vec2::new(x_pos, y_pos)),
+            WallLocation::Block(cell) => {
+                let point = Vec2::new(cell.x as f32, cell.y as f32);
+
+                (point, point)
+            }
         }
     }
 }
 
 #[derive(Resource)]
-struct Scoreboard {
-    score: usize,
-    difficulty: usize,
+pub struct Scoreboard {
+    pub score: usize,
+    pub scores: Vec<usize>,
+    pub difficulty: usize,
+}
+
+impl Scoreboard {
+    fn new(game: &SnakeSim) -> Scoreboard {
+        Scoreboard {
+            score: 0,
+            scores: vec![0; game.rules().players],
+            difficulty: game.difficulty(),
+        }
+    }
+
+    pub(crate) fn sync(&mut self, game: &SnakeSim) {
+        self.score = game.score();
+        for (i, score) in self.scores.iter_mut().enumerate() {
+            *score = game.snake(i).score();
+        }
+        self.difficulty = game.difficulty();
+    }
+
+    fn score_text(&self) -> String {
+        if self.scores.len() < 2 {
+            return self.score.to_string();
+        }
+
+        let scores: Vec<String> = self.scores.iter()
+            .enumerate()
+            .map(|(i, score)| format!("P{} {}", i + 1, score))
+            .collect();
+
+        scores.join("  ")
+    }
+}
+
+pub(crate) fn food_color(config: &Config, food: Food) -> Color {
+    match food {
+        Food::Mouse => config.colors.mouse,
+        Food::GoldenMouse => config.colors.golden_mouse,
+        Food::Poison => config.colors.poison,
+        Food::SpeedUp => config.colors.speed_up,
+        Food::SlowDown => config.colors.slow_down,
+    }.into()
+}
+
+pub(crate) fn power_up_color(config: &Config, power_up: PowerUp) -> Color {
+    match power_up {
+        PowerUp::Ghost => config.colors.ghost,
+        PowerUp::SlowMotion => config.colors.slow_motion,
+        PowerUp::Magnet => config.colors.magnet,
+        PowerUp::Shield => config.colors.shield,
+    }.into()
+}
+
+// Snakes fade from head to tail, each player along a different channel so they are easy to tell apart
+pub(crate) fn snake_color(config: &Config, snake: usize, shade: f32) -> Color {
+    if config.rules.is_rival(snake) {
+        let mut color: Color = config.colors.rival.into();
+        color.set_r(shade);
+        color
+    } else if snake == 0 {
+        let mut color: Color = config.colors.snake.into();
+        color.set_r(shade);
+        color
+    } else {
+        let mut color: Color = config.colors.second_snake.into();
+        color.set_g(shade);
+        color
+    }
 }
 
 #[derive(Component)]
-struct ScoreboardComponent;
+pub(crate) struct ScoreboardComponent;
 
 #[derive(Resource)]
 struct Sounds {
-    sounds: HashMap<SoundType, Handle<AudioSource>>
+    sounds: HashMap<SoundType, (Handle<AudioSource>, f32)>
 }
 
 impl Sounds {
@@ -317,22 +492,23 @@ impl Sounds {
         Sounds { sounds: HashMap::new() }
     }
 
-    fn add_sound(&mut self, sound_type: SoundType, source: Handle<AudioSource>) {
-        self.sounds.insert(sound_type, source);
+    fn add_sound(&mut self, sound_type: SoundType, source: Handle<AudioSource>, speed: f32) {
+        self.sounds.insert(sound_type, (source, speed));
     }
 
-    fn get_sound(&self, sound_type: &SoundType) -> Option<Handle<AudioSource>> {
-        match self.sounds.get(sound_type) {
-            None => None,
-            Some(sound) => Some(sound.clone()),
-        }
+    fn get_sound(&self, sound_type: &SoundType) -> Option<(Handle<AudioSource>, f32)> {
+        self.sounds.get(sound_type).cloned()
     }
 }
 
 #[derive(PartialEq, Eq, Hash)]
 enum SoundType {
     Silence,
-    Grow,
+    Eat(Food),
+    Bonus,
+    BonusExpired,
+    PowerUp,
+    Shielded,
     DifficultyUp,
     Failure,
 }
@@ -346,54 +522,81 @@ impl Default for SoundEvent {
     }
 }
 
-fn setup_once(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_once(mut commands: Commands, asset_server: Option<Res<AssetServer>>, config: Res<Config>) {
     // Camera
     commands.spawn(Camera2dBundle::default());
 
-    // Sounds
+    // Sounds, left empty when muted or running without assets
     let mut sounds = Sounds::new();
 
-    let grow_sound = asset_server.load("sounds/grow.mp3");
-    sounds.add_sound(SoundType::Grow, grow_sound);
+    let Some(asset_server) = asset_server else {
+        commands.insert_resource(sounds);
+        return;
+    };
+
+    // Level
+    if let Some(level) = &config.level {
+        commands.insert_resource(LevelHandle(asset_server.load(level.clone())));
+    }
+
+    if config.audio.muted {
+        commands.insert_resource(sounds);
+        return;
+    }
+
+    for (food, path, speed) in FOOD_SOUNDS {
+        sounds.add_sound(SoundType::Eat(food), asset_server.load(path), speed);
+    }
+
+    sounds.add_sound(SoundType::Bonus, asset_server.load("sounds/grow.mp3"), 2.0);
+    sounds.add_sound(SoundType::BonusExpired, asset_server.load("sounds/failure.mp3"), 2.0);
+    sounds.add_sound(SoundType::PowerUp, asset_server.load("sounds/difficulty_up.mp3"), 1.3);
+    sounds.add_sound(SoundType::Shielded, asset_server.load("sounds/failure.mp3"), 1.4);
 
     let difficulty_up_sound = asset_server.load("sounds/difficulty_up.mp3");
-    sounds.add_sound(SoundType::DifficultyUp, difficulty_up_sound);
+    sounds.add_sound(SoundType::DifficultyUp, difficulty_up_sound, 1.0);
 
     let failure_sound = asset_server.load("sounds/failure.mp3");
-    sounds.add_sound(SoundType::Failure, failure_sound);
+    sounds.add_sound(SoundType::Failure, failure_sound, 1.0);
 
     commands.insert_resource(sounds);
 }
 
-fn setup(mut commands: Commands) {
-    // Walls
-    commands.spawn(WallBundle::new(WallLocation::Left, BLOCK_SIZE));
-    commands.spawn(WallBundle::new(WallLocation::Top, BLOCK_SIZE));
-    commands.spawn(WallBundle::new(WallLocation::Right, BLOCK_SIZE));
-    commands.spawn(WallBundle::new(WallLocation::Bottom, BLOCK_SIZE));
+fn setup(
+    mut commands: Commands,
+    game: Res<Game>,
+    scoreboard: Res<Scoreboard>,
+    high_scores: Res<HighScores>,
+    autopilot: Res<Autopilot>,
+    config: Res<Config>,
+) {
+    let block_size = config.block_scale();
+    let scoreboard_color = config.colors.scoreboard.into();
+
+    // Walls, the snake goes right through the edges in the wrap mode
+    if game.rules().mode != GameMode::Wrap {
+        commands.spawn(WallBundle::new(WallLocation::Left, &config));
+        commands.spawn(WallBundle::new(WallLocation::Top, &config));
+        commands.spawn(WallBundle::new(WallLocation::Right, &config));
+        commands.spawn(WallBundle::new(WallLocation::Bottom, &config));
+    }
+    for &cell in &game.rules().walls {
+        commands.spawn(WallBundle::new(WallLocation::Block(cell), &config));
+    }
 
     // Mouse
-    commands.spawn(MouseBundle::new(BLOCK_SIZE));
-
-    // Snake
-    let delta = 1.0 / SNAKE_STARTING_LENGTH as f32;
-    let blocks_offset = SNAKE_STARTING_DIRECTION.reverse().velocity();
-    let mut color = SNAKE_COLOR;
-    for i in 0..SNAKE_STARTING_LENGTH {
-        color.set_r(delta * i as f32);
-
-        commands.spawn(SnakeBundle::new(
-            i as u32,
-            BlockBundle::new(
-                color,
-                Position::new(
-                    SNAKE_STARTING_POSITION.x + i as f32 * blocks_offset.x,
-                    SNAKE_STARTING_POSITION.y + i as f32 * blocks_offset.y,
-                ),
-                BLOCK_SIZE,
-            ),
-            SNAKE_STARTING_DIRECTION,
-        ));
+    commands.spawn(MouseBundle::new(food_color(&config, game.food()), game.mouse().into(), block_size));
+    commands.spawn(BonusBundle::new(config.colors.bonus.into(), block_size));
+    commands.spawn(PowerUpBundle::new(block_size));
+
+    // Snakes
+    for (id, snake) in game.snakes().iter().enumerate() {
+        let delta = 1.0 / snake.body().len() as f32;
+        for (i, &cell) in snake.body().iter().enumerate() {
+            let color = snake_color(&config, id, delta * i as f32);
+
+            commands.spawn(SnakeBundle::new(id, i, BlockBundle::new(color, cell.into(), block_size)));
+        }
     }
 
     // Scoreboard
@@ -403,15 +606,15 @@ fn setup(mut commands: Commands) {
                 "Score: ",
                 TextStyle {
                     font_size: SCOREBOARD_FONT_SIZE,
-                    color: SCOREBOARD_COLOR,
+                    color: scoreboard_color,
                     ..default()
                 },
             ),
             TextSection::new(
-                "0",
+                scoreboard.score_text(),
                 TextStyle {
                     font_size: SCOREBOARD_FONT_SIZE,
-                    color: SCOREBOARD_COLOR,
+                    color: scoreboard_color,
                     ..default()
                 },
             ),
@@ -419,15 +622,63 @@ fn setup(mut commands: Commands) {
                 "\nDifficulty: ",
                 TextStyle {
                     font_size: SCOREBOARD_FONT_SIZE,
-                    color: SCOREBOARD_COLOR,
+                    color: scoreboard_color,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                scoreboard.difficulty.to_string(),
+                TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: scoreboard_color,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                "\nSeed: ",
+                TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: scoreboard_color,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                game.seed().to_string(),
+                TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: scoreboard_color,
                     ..default()
                 },
             ),
             TextSection::new(
-                "0",
+                "\nBest: ",
                 TextStyle {
                     font_size: SCOREBOARD_FONT_SIZE,
-                    color: SCOREBOARD_COLOR,
+                    color: scoreboard_color,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                high_scores.best(game.rules().into()).to_string(),
+                TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: scoreboard_color,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                autopilot_text(&autopilot),
+                TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: scoreboard_color,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                effects_text(&game),
+                TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: scoreboard_color,
                     ..default()
                 },
             ),
@@ -443,171 +694,447 @@ fn setup(mut commands: Commands) {
 }
 
 fn handle_state_input(
-    keys: Res<Input<KeyCode>>,
+    actions: Actions,
+    pad_buttons: Res<Input<GamepadButton>>,
     state: Res<State<GameState>>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
+    // Start pauses and unpauses like the pause action, the south button (A on most pads) only starts and restarts
+    let start = actions.just_pressed(Action::Pause) || gamepad::any_just_pressed(&pad_buttons, &[GamepadButtonType::Start]);
+    let play = start || gamepad::any_just_pressed(&pad_buttons, &[GamepadButtonType::South]);
+    let restart = actions.just_pressed(Action::Restart)
+        || gamepad::any_just_pressed(&pad_buttons, &[GamepadButtonType::Start, GamepadButtonType::South]);
+
     match state.get() {
-        GameState::Startup if keys.just_pressed(KeyCode::Space) => next_state.set(GameState::Running),
-        GameState::Running if keys.just_pressed(KeyCode::Space) => next_state.set(GameState::Paused),
-        GameState::Paused if keys.just_pressed(KeyCode::Space) => next_state.set(GameState::Running),
-        GameState::GameOver if keys.just_pressed(KeyCode::R) => next_state.set(GameState::Running),
+        GameState::Startup if play => next_state.set(GameState::Running),
+        GameState::Running if start => next_state.set(GameState::Paused),
+        GameState::Paused if start => next_state.set(GameState::Running),
+        GameState::GameOver if restart => next_state.set(GameState::Running),
+        GameState::Startup | GameState::GameOver if actions.just_pressed(Action::Editor) => next_state.set(GameState::Editor),
+        GameState::Startup if actions.just_pressed(Action::Leaderboard) => next_state.set(GameState::Leaderboard),
+        GameState::Startup if actions.just_pressed(Action::Bindings) => next_state.set(GameState::Bindings),
+        GameState::Leaderboard if actions.just_pressed(Action::Leaderboard) || start => next_state.set(GameState::Startup),
         _ => {}
     };
 }
 
+// Goes through the presets before the game starts, rules tuned by hand switch to the first one
+fn select_preset(actions: Actions, mut config: ResMut<Config>, mut selected: EventWriter<PresetSelected>) {
+    if !actions.just_pressed(Action::Difficulty) {
+        return;
+    }
+
+    let preset = DifficultyPreset::of(&config.rules).map_or(DifficultyPreset::Easy, DifficultyPreset::next);
+    preset.apply(&mut config.rules);
+
+    selected.send(PresetSelected);
+}
+
+fn toggle_autopilot(actions: Actions, mut autopilot: ResMut<Autopilot>) {
+    if actions.just_pressed(Action::Autopilot) {
+        autopilot.0 = !autopilot.0;
+    }
+}
+
+// The rebinding screen uses the escape key for itself
+fn quit(actions: Actions, state: Res<State<GameState>>, mut exit: EventWriter<AppExit>) {
+    if *state.get() != GameState::Bindings && actions.just_pressed(Action::Quit) {
+        exit.send(AppExit);
+    }
+}
+
+fn queue_input(
+    actions: Actions,
+    game: Res<Game>,
+    config: Res<Config>,
+    autopilot: Res<Autopilot>,
+    mut pad_turns: EventReader<PadTurn>,
+    mut direction_queues: ResMut<DirectionQueues>,
+) {
+    let players = game.rules().players;
+    direction_queues.resize(players, VecDeque::new());
+
+    // Without a second player both sets of keys steer the first one
+    let directions: Vec<(usize, Direction)> = actions.turns()
+        .into_iter()
+        .map(|(player, direction)| (player.min(players - 1), direction))
+        .collect();
+
+    // Gamepads steer alongside the keyboard, one player each
+    let pad_directions = pad_turns.read()
+        .filter(|turn| turn.pad < players)
+        .map(|turn| (turn.pad, turn.direction));
+
+    for (player, direction) in directions.into_iter().chain(pad_directions) {
+        // The autopilot has the first snake's controls to itself
+        if player == 0 && autopilot.0 {
+            continue;
+        }
+
+        let queue = &mut direction_queues[player];
+        if queue.len() < config.input.max_queue_length {
+            queue.push_back(direction);
+        }
+    }
+}
+
+fn autopilot(game: Res<Game>, autopilot: Res<Autopilot>, mut direction_queues: ResMut<DirectionQueues>) {
+    // Decide once per move, the game does not change until the queued direction is used up
+    if !autopilot.0 || !direction_queues[0].is_empty() {
+        return;
+    }
+
+    if let Some(direction) = ai::autopilot_direction(&game, 0) {
+        direction_queues[0].push_back(direction);
+    }
+}
+
 fn move_snake(
-    keys: Res<Input<KeyCode>>,
-    mut query: Query<(&mut Transform, &mut Position, &mut Direction), With<Snake>>,
     time: Res<Time>,
+    mut game: ResMut<Game>,
     mut timer: ResMut<MoveTimer>,
-    mut direction_queue: Local<VecDeque<Direction>>,
+    mut recording: ResMut<Recording>,
+    mut direction_queues: ResMut<DirectionQueues>,
+    mut step_events: EventWriter<StepEvent>,
+    mut sound_events: EventWriter<SoundEvent>,
 ) {
     timer.tick(time.delta());
 
-    {
-        // Handle keyboard controls
-        let (_, _, mut head_dir) = query.iter_mut().next().unwrap();
-
-        let directions: Vec<Direction> = keys.get_just_pressed().filter_map(|k| match k {
-            KeyCode::Left | KeyCode::A => Some(Direction::Left),
-            KeyCode::Right | KeyCode::D => Some(Direction::Right),
-            KeyCode::Up | KeyCode::W => Some(Direction::Up),
-            KeyCode::Down | KeyCode::S => Some(Direction::Down),
-            _ => None,
-        }).collect();
-
-        for direction in &directions {
-            if direction_queue.len() == MAX_INPUT_QUEUE_LENGTH {
-                break;
-            }
+    // Move the snakes
+    if timer.just_finished() {
+        let mut inputs: Vec<Option<Direction>> = direction_queues.iter_mut()
+            .enumerate()
+            .map(|(player, queue)| {
+                let snake = game.snake(player);
+                std::iter::from_fn(|| queue.pop_front()).find(|&d| snake.can_turn(d))
+            })
+            .collect();
+
+        let rules = game.rules();
+        for rival in rules.players..rules.snake_count() {
+            inputs.push(ai::choose_direction(&game, rival, rules.rival_behaviour));
+        }
 
-            direction_queue.push_back(*direction);
+        recording.record(&game, &inputs);
+        for (id, outcome) in game.step(&inputs) {
+            step_events.send(StepEvent(id, outcome));
         }
+        if game.bonus_expired() {
+            sound_events.send(SoundEvent(SoundType::BonusExpired));
+        }
+    }
+}
 
-        if timer.just_finished() {
-            while !direction_queue.is_empty() {
-                let d = direction_queue.pop_front().unwrap();
+// New records only get a name when they are kept on disk
+#[derive(SystemParam)]
+struct NameCheck<'w> {
+    autopilot: Res<'w, Autopilot>,
+    high_scores: Res<'w, HighScores>,
+    path: Option<Res<'w, HighScoresPath>>,
+}
 
-                if d.reverse() != *head_dir {
-                    *head_dir = d;
-                    break;
+impl NameCheck<'_> {
+    fn needs_name(&self, game: &SnakeSim) -> bool {
+        self.path.is_some() && is_high_score(game, &self.autopilot, &self.high_scores)
+    }
+}
+
+fn check_collisions(
+    game: Res<Game>,
+    name_check: NameCheck,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut state: ResMut<NextState<GameState>>,
+    mut sound_events: EventWriter<SoundEvent>,
+    mut step_events: EventReader<StepEvent>,
+) {
+    for StepEvent(id, outcome) in step_events.read() {
+        match outcome {
+            StepOutcome::Moved => {}
+            StepOutcome::Ate(food, _) | StepOutcome::DifficultyUp(food, _) => {
+                scoreboard.score = game.score();
+                if let Some(score) = scoreboard.scores.get_mut(*id) {
+                    *score = game.snake(*id).score();
+                }
+                sound_events.send(SoundEvent(SoundType::Eat(*food)));
+            }
+            StepOutcome::AteBonus(_) => {
+                scoreboard.score = game.score();
+                if let Some(score) = scoreboard.scores.get_mut(*id) {
+                    *score = game.snake(*id).score();
                 }
+                sound_events.send(SoundEvent(SoundType::Bonus));
             }
+            StepOutcome::PickedUp(_) => sound_events.send(SoundEvent(SoundType::PowerUp)),
+            StepOutcome::Shielded => sound_events.send(SoundEvent(SoundType::Shielded)),
+            // If collided with a wall or a snake and nobody is left to play, stop the game
+            StepOutcome::Died if game.is_game_over() => {
+                if name_check.needs_name(&game) {
+                    state.set(GameState::NameEntry);
+                } else {
+                    state.set(GameState::GameOver);
+                }
+            }
+            StepOutcome::Died => sound_events.send(SoundEvent(SoundType::Failure)),
         }
     }
+}
 
-    // Move the snake
-    if timer.just_finished() {
-        let mut prev_dir = None;
-        for (mut transform, mut pos, mut dir) in query.iter_mut() {
-            pos.apply_vel(&dir.velocity());
-            transform.translation = pos.translation();
-
-            if let Some(d) = prev_dir {
-                prev_dir = Some(dir.clone());
-                *dir = d.clone();
-            } else {
-                prev_dir = Some(dir.clone());
-            }
+// Every block has a transform to move, the filters keep the queries apart
+type SnakeBlocks = (Without<Mouse>, Without<Bonus>, Without<PowerUpBlock>);
+type MouseBlock = (With<Mouse>, Without<Bonus>, Without<PowerUpBlock>);
+type BonusBlock = (With<Bonus>, Without<PowerUpBlock>);
+
+#[derive(Component)]
+struct ScorePopup(Timer);
+
+fn spawn_popups(
+    mut commands: Commands,
+    game: Res<Game>,
+    config: Res<Config>,
+    mut step_events: EventReader<StepEvent>,
+) {
+    for StepEvent(id, outcome) in step_events.read() {
+        let (points, combo) = match outcome {
+            StepOutcome::Ate(_, points) | StepOutcome::DifficultyUp(_, points) => (*points, game.combo_multiplier(*id)),
+            StepOutcome::AteBonus(points) => (*points, 0),
+            _ => continue,
+        };
+        if points == 0 {
+            continue;
         }
+
+        let text = if combo > 1 { format!("+{} x{}", points, combo) } else { format!("+{}", points) };
+        let position: Position = game.snake(*id).head().into();
+
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(text, TextStyle {
+                    font_size: POPUP_FONT_SIZE,
+                    color: config.colors.scoreboard.into(),
+                    ..default()
+                }),
+                transform: Transform::from_translation(position.translation(config.block_scale()) + Vec3::Z),
+                ..default()
+            },
+            ScorePopup(Timer::new(POPUP_DURATION, TimerMode::Once)),
+            GameComponents,
+        ));
     }
 }
 
-fn check_collisions(
+fn float_popups(
     mut commands: Commands,
-    mut scoreboard: ResMut<Scoreboard>,
-    mut state: ResMut<NextState<GameState>>,
-    mut sound_events: EventWriter<SoundEvent>,
-    snake_query: Query<(&Snake, &Transform, &Position, &Direction), With<Snake>>,
-    collider_query: Query<(Entity, &Transform, Option<&Snake>, Option<&Mouse>), With<Collider>>,
+    time: Res<Time>,
+    config: Res<Config>,
+    mut query: Query<(Entity, &mut ScorePopup, &mut Transform, &mut Text)>,
 ) {
-    let snake: Vec<(&Snake, &Transform, &Position, &Direction)> = snake_query.iter().collect();
+    for (entity, mut popup, mut transform, mut text) in query.iter_mut() {
+        if popup.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
 
-    let (head, head_transform, _, _) = snake.first().unwrap();
+        transform.translation.y += POPUP_RISE * config.display.block_size * time.delta_seconds();
+        text.sections[0].style.color.set_a(popup.0.percent_left());
+    }
+}
+
+pub(crate) fn update_blocks(
+    mut commands: Commands,
+    game: Res<Game>,
+    config: Res<Config>,
+    mut snake_query: Query<(Entity, &Snake, &mut Transform, &mut Position), SnakeBlocks>,
+    mut mouse_query: Query<(&mut Transform, &mut Position, &mut Sprite), MouseBlock>,
+    mut bonus_query: Query<(&mut Transform, &mut Position, &mut Visibility), BonusBlock>,
+    mut power_up_query: Query<(&mut Transform, &mut Position, &mut Visibility, &mut Sprite), With<PowerUpBlock>>,
+) {
+    if !game.is_changed() {
+        return;
+    }
 
-    for (entity, transform, maybe_snake, maybe_mouse) in collider_query.iter() {
-        // Do not collide snake head with itself
-        if let Some(snake) = maybe_snake {
-            if snake.0 == head.0 {
-                continue;
+    let block_size = config.block_scale();
+    let mut segments = vec![0; game.snakes().len()];
+    for (entity, snake, mut transform, mut position) in snake_query.iter_mut() {
+        match game.snake(snake.id).body().get(snake.segment) {
+            Some(&cell) => {
+                *position = cell.into();
+                transform.translation = position.translation(block_size);
+                segments[snake.id] += 1;
             }
+            // The snake died and was taken off the board
+            None => commands.entity(entity).despawn(),
         }
+    }
 
-        let collision = collide(
-            head_transform.translation,
-            head_transform.scale.truncate(),
-            transform.translation,
-            transform.scale.truncate(),
-        );
-
-        if let Some(_) = collision {
-            // If collided with mouse, spawn a new one
-            if maybe_mouse.is_some() {
-                scoreboard.score += SCORE_DELTA;
-
-                commands.entity(entity).despawn();
-
-                let mut mouse_bundle = MouseBundle::new(BLOCK_SIZE);
-                // Check if we are trying to spawn a mouse inside the snake
-                while snake.iter().find(|(_, _, position, _)| {
-                    position.x == mouse_bundle.block_bundle.position.x
-                        && position.y == mouse_bundle.block_bundle.position.y
-                }).is_some() {
-                    mouse_bundle = MouseBundle::new(BLOCK_SIZE);
-                }
+    // Spawn new snake blocks the simulation has grown since the last update
+    for (id, snake) in game.snakes().iter().enumerate() {
+        for (i, &cell) in snake.body().iter().enumerate().skip(segments[id]) {
+            let color = snake_color(&config, id, 1.0);
 
-                commands.spawn(mouse_bundle);
-
-                // Spawn a new snake block behind the current tail block
-                let (tail, _, tail_position, &tail_direction) = snake.last().unwrap();
-                let pos_offset = tail_direction.reverse().velocity();
-                commands.spawn(SnakeBundle::new(
-                    tail.0 + 1,
-                    BlockBundle::new(
-                        SNAKE_COLOR,
-                        Position::new(
-                            tail_position.x + pos_offset.x,
-                            tail_position.y + pos_offset.y,
-                        ),
-                        BLOCK_SIZE,
-                    ),
-                    tail_direction,
-                ));
-
-                sound_events.send(SoundEvent(SoundType::Grow));
-
-                return;
-            }
+            commands.spawn(SnakeBundle::new(id, i, BlockBundle::new(color, cell.into(), block_size)));
+        }
+    }
+
+    let (mut transform, mut position, mut sprite) = mouse_query.single_mut();
+    *position = game.mouse().into();
+    transform.translation = position.translation(block_size);
+    sprite.color = food_color(&config, game.food());
+
+    let (mut transform, mut position, mut visibility) = bonus_query.single_mut();
+    match game.bonus() {
+        Some(bonus) => {
+            let left = bonus.ticks_left as f32 / game.rules().bonus_lifetime as f32;
+
+            *position = bonus.cell.into();
+            transform.translation = position.translation(block_size);
+            transform.scale = block_size * (BONUS_MIN_SCALE + (1.0 - BONUS_MIN_SCALE) * left);
+            *visibility = blink(bonus.ticks_left);
+        }
+        None => *visibility = Visibility::Hidden,
+    }
 
-            // If collided with wall or snake itself, stop the game
-            state.set(GameState::GameOver);
+    let (mut transform, mut position, mut visibility, mut sprite) = power_up_query.single_mut();
+    match game.power_up() {
+        Some(item) => {
+            *position = item.cell.into();
+            transform.translation = position.translation(block_size);
+            sprite.color = power_up_color(&config, item.power_up);
+            *visibility = blink(item.ticks_left);
         }
+        None => *visibility = Visibility::Hidden,
     }
 }
 
-fn update_scoreboard(scoreboard: Res<Scoreboard>, mut query: Query<&mut Text, With<ScoreboardComponent>>) {
+fn blink(ticks_left: usize) -> Visibility {
+    if ticks_left <= BLINK_TICKS && ticks_left % 2 == 1 {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    }
+}
+
+fn fit_camera(
+    config: Res<Config>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut OrthographicProjection>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+
+    // Zoom out when the arena does not fit into the window, but never zoom in
+    let arena = config.arena_size();
+    let scale = (arena.x / window.width()).max(arena.y / window.height()).max(1.0);
+
+    for mut projection in camera_query.iter_mut() {
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+    }
+}
+
+fn level_loaded(mut level_events: EventReader<AssetEvent<LevelAsset>>, level: Option<Res<LevelHandle>>) -> bool {
+    let Some(level) = level else {
+        return false;
+    };
+
+    // Also pick up edits to the level file
+    level_events.read().fold(false, |loaded, event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => loaded || *id == level.0.id(),
+        _ => loaded,
+    })
+}
+
+fn queue_level(mut pending: ResMut<PendingLevel>) {
+    pending.0 = true;
+}
+
+fn apply_level(
+    mut config: ResMut<Config>,
+    mut pending: ResMut<PendingLevel>,
+    level: Res<LevelHandle>,
+    levels: Res<Assets<LevelAsset>>,
+    mut applied: EventWriter<LevelApplied>,
+) {
+    if !pending.0 {
+        return;
+    }
+    let Some(level) = levels.get(&level.0) else {
+        return;
+    };
+    pending.0 = false;
+
+    let mut rules = config.rules.clone();
+    level.apply(&mut rules);
+
+    // A broken level leaves the game as it is
+    match rules.validate() {
+        Ok(()) => {
+            config.rules = rules;
+            applied.send(LevelApplied);
+        }
+        Err(reason) => error!("cannot use level {:?}: {}", config.level, reason),
+    }
+}
+
+pub(crate) fn update_scoreboard(
+    game: Res<Game>,
+    scoreboard: Res<Scoreboard>,
+    high_scores: Res<HighScores>,
+    autopilot: Res<Autopilot>,
+    mut query: Query<&mut Text, With<ScoreboardComponent>>,
+) {
     let mut text = query.single_mut();
-    text.sections[1].value = scoreboard.score.to_string();
+    text.sections[1].value = scoreboard.score_text();
     text.sections[3].value = scoreboard.difficulty.to_string();
+    let best = high_scores.best(game.rules().into()).max(scoreboard.scores.first().copied().unwrap_or(0));
+    text.sections[7].value = best.to_string();
+    text.sections[8].value = autopilot_text(&autopilot);
+    text.sections[9].value = effects_text(&game);
+}
+
+// The power-ups the players have going and the seconds they have left
+fn effects_text(game: &SnakeSim) -> String {
+    let players = game.rules().players;
+    let tick = game.tick_duration().as_secs_f32();
+
+    (0..players)
+        .filter(|&i| !game.snake(i).effects().is_empty())
+        .map(|i| {
+            let effects: Vec<String> = game.snake(i).effects().iter()
+                .map(|effect| format!("{} {:.1}s", effect.power_up.to_string().to_uppercase(), effect.ticks_left as f32 * tick))
+                .collect();
+
+            if players > 1 { format!("\nP{} {}", i + 1, effects.join("  ")) } else { format!("\n{}", effects.join("  ")) }
+        })
+        .collect()
+}
+
+fn autopilot_text(autopilot: &Autopilot) -> String {
+    if autopilot.0 {
+        String::from("\nAUTOPILOT")
+    } else {
+        String::new()
+    }
 }
 
 fn update_difficulty(
+    game: Res<Game>,
     mut scoreboard: ResMut<Scoreboard>,
     mut timer: ResMut<MoveTimer>,
     mut sound_events: EventWriter<SoundEvent>,
 ) {
-    let difficulty = (scoreboard.score as f32 / SCORE_DIFFICULTY_THRESHOLD).floor() as usize;
-
-    if difficulty != scoreboard.difficulty {
-        scoreboard.difficulty = difficulty;
-
-        let new_duration = timer.duration().as_secs_f32() * (1.0 - TIMER_SCALING_PERCENTAGE / 100.0);
-
-        timer.set_duration(Duration::from_secs_f32(new_duration));
+    // A bonus can make the game harder as well as a mouse
+    if scoreboard.difficulty != game.difficulty() {
+        scoreboard.difficulty = game.difficulty();
 
         sound_events.send(SoundEvent(SoundType::DifficultyUp));
     }
+
+    // Speed food changes the pace without changing the difficulty
+    if timer.duration() != game.tick_duration() {
+        timer.set_duration(game.tick_duration());
+    }
 }
 
 fn play_sounds(
@@ -617,16 +1144,55 @@ fn play_sounds(
 ) {
     if !sound_events.is_empty() {
         for sound_event in sound_events.read() {
-            if let Some(sound) = sounds.get_sound(&sound_event.0) {
+            if let Some((sound, speed)) = sounds.get_sound(&sound_event.0) {
                 commands.spawn(AudioBundle {
                     source: sound,
-                    settings: PlaybackSettings::DESPAWN,
+                    settings: PlaybackSettings::DESPAWN.with_speed(speed),
                 });
             }
         }
     }
 }
 
+// Only single player games by a human make it into the high scores
+fn is_high_score(game: &SnakeSim, autopilot: &Autopilot, high_scores: &HighScores) -> bool {
+    game.rules().players == 1 && !autopilot.0 && high_scores.rank(game.rules().into(), game.score()).is_some()
+}
+
+fn record_high_score(
+    mut commands: Commands,
+    game: Res<Game>,
+    autopilot: Res<Autopilot>,
+    name: Option<Res<EnteredName>>,
+    path: Option<Res<HighScoresPath>>,
+    mut high_scores: ResMut<HighScores>,
+    mut rank: ResMut<HighScoreRank>,
+) {
+    rank.0 = None;
+    if !is_high_score(&game, &autopilot, &high_scores) {
+        return;
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    rank.0 = high_scores.insert(HighScore {
+        name: name.map_or_else(String::new, |name| name.0.clone()),
+        score: game.score(),
+        length: game.snake(0).body().len(),
+        difficulty: game.difficulty(),
+        mode: game.rules().mode,
+        board: game.rules().board,
+        seed: game.seed(),
+        timestamp,
+    });
+    commands.remove_resource::<EnteredName>();
+
+    if let (Some(_), Some(path)) = (rank.0, path) {
+        if let Err(err) = high_scores.save(&path.0) {
+            error!("{}", err);
+        }
+    }
+}
+
 fn game_over(mut sound_events: EventWriter<SoundEvent>) {
     sound_events.send(SoundEvent(SoundType::Failure))
 }
@@ -637,21 +1203,63 @@ fn despawn<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>)
     }
 }
 
-fn reset(mut scoreboard: ResMut<Scoreboard>, mut timer: ResMut<MoveTimer>) {
-    scoreboard.score = 0;
-    scoreboard.difficulty = 0;
-    timer.set_duration(Duration::from_secs_f32(TIMER_STARTING_DURATION));
+fn reset(
+    mut game: ResMut<Game>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut timer: ResMut<MoveTimer>,
+    mut recording: ResMut<Recording>,
+    config: Res<Config>,
+    seed: Option<Res<Seed>>,
+) {
+    // A fixed seed replays the same game on every restart, otherwise pick a fresh one
+    game.0 = SnakeSim::new(config.rules.clone(), seed.map_or_else(rand::random, |seed| seed.0));
+    recording.0 = Replay::new(config.rules.clone(), game.seed());
+    *scoreboard = Scoreboard::new(&game);
+    timer.set_duration(game.tick_duration());
 }
 
 #[derive(Component, Default)]
 struct StartupMessage;
 
 impl Message for StartupMessage {
-    fn get_message() -> String {
-        String::from(r#"USE WASD OR ARROW KEYS TO CONTROL THE SNAKE
-PRESS SPACE TO PAUSE OR UNPAUSE THE GAME
-PRESS ESC TO EXIT
-PRESS SPACE TO CONTINUE"#)
+    fn get_message(context: &MessageContext) -> String {
+        let bindings = &context.bindings;
+        let first = steering_text(bindings, [Action::TurnUp, Action::TurnLeft, Action::TurnDown, Action::TurnRight]);
+        let second = steering_text(bindings, [
+            Action::SecondTurnUp,
+            Action::SecondTurnLeft,
+            Action::SecondTurnDown,
+            Action::SecondTurnRight,
+        ]);
+        let controls = if context.game.rules().players > 1 {
+            format!("PLAYER 1 USES {}, PLAYER 2 {}", first, second)
+        } else {
+            format!("USE {} OR {} TO CONTROL THE SNAKE", first, second)
+        };
+
+        format!(
+            "{}
+PRESS {} TO PAUSE OR UNPAUSE THE GAME
+GAMEPADS STEER WITH THE D-PAD OR STICK, START PAUSES
+PRESS {} TO TOGGLE THE AUTOPILOT
+PRESS {} TO OPEN THE LEVEL EDITOR
+PRESS {} FOR THE LEADERBOARD
+PRESS {} TO CHANGE THE KEYS
+PRESS {} TO CHANGE THE DIFFICULTY: {}
+PRESS {} TO EXIT
+PRESS {} TO CONTINUE{}",
+            controls,
+            bindings.keys_text(Action::Pause),
+            bindings.keys_text(Action::Autopilot),
+            bindings.keys_text(Action::Editor),
+            bindings.keys_text(Action::Leaderboard),
+            bindings.keys_text(Action::Bindings),
+            bindings.keys_text(Action::Difficulty),
+            DifficultyPreset::of(context.game.rules()).map_or(String::from("CUSTOM"), |preset| preset.to_string().to_uppercase()),
+            bindings.keys_text(Action::Quit),
+            bindings.keys_text(Action::Pause),
+            high_scores_text(&context.high_scores, context.game.rules().into()),
+        )
     }
 }
 
@@ -659,7 +1267,7 @@ PRESS SPACE TO CONTINUE"#)
 struct PausedMessage;
 
 impl Message for PausedMessage {
-    fn get_message() -> String {
+    fn get_message(_context: &MessageContext) -> String {
         String::from("PAUSED")
     }
 }
@@ -668,50 +1276,119 @@ impl Message for PausedMessage {
 struct GameOverMessage;
 
 impl Message for GameOverMessage {
-    fn get_message() -> String {
-        String::from("GAME OVER\nPRESS R TO RESTART OR ESC TO EXIT")
+    fn get_message(context: &MessageContext) -> String {
+        let game = &context.game;
+        let players = &game.snakes()[..game.rules().players];
+        let result = if players.len() < 2 {
+            match context.rank.0 {
+                Some(0) => String::from("GAME OVER\nNEW HIGH SCORE!"),
+                Some(rank) => format!("GAME OVER\n#{} IN THE HIGH SCORES", rank + 1),
+                None => String::from("GAME OVER"),
+            }
+        } else {
+            let scores: Vec<String> = players.iter()
+                .enumerate()
+                .map(|(i, snake)| format!("P{} {}", i + 1, snake.score()))
+                .collect();
+
+            match game.winner() {
+                Some(i) => format!("PLAYER {} WINS\n{}", i + 1, scores.join("  ")),
+                None => format!("DRAW\n{}", scores.join("  ")),
+            }
+        };
+
+        let bindings = &context.bindings;
+
+        format!(
+            "{}\nPRESS {} TO RESTART, {} TO EDIT THE LEVEL, {} TO SAVE THE REPLAY OR {} TO EXIT{}",
+            result,
+            bindings.keys_text(Action::Restart),
+            bindings.keys_text(Action::Editor),
+            bindings.keys_text(Action::SaveReplay),
+            bindings.keys_text(Action::Quit),
+            high_scores_text(&context.high_scores, game.rules().into()),
+        )
     }
 }
 
+// The keys for up, left, down and right, e.g. "W A S D"
+fn steering_text(bindings: &Bindings, actions: [Action; 4]) -> String {
+    let keys: Vec<String> = actions.iter().map(|&action| bindings.keys_text(action)).collect();
+
+    keys.join(" ")
+}
+
+fn high_scores_text(high_scores: &HighScores, table: Table) -> String {
+    let scores: Vec<String> = high_scores.table(table)
+        .take(MESSAGE_HIGH_SCORES)
+        .map(|entry| entry.score.to_string())
+        .collect();
+    if scores.is_empty() {
+        return String::new();
+    }
+
+    format!("\nHIGH SCORES: {}", scores.join("  "))
+}
+
+#[derive(SystemParam)]
+struct MessageContext<'w> {
+    game: Res<'w, Game>,
+    high_scores: Res<'w, HighScores>,
+    rank: Res<'w, HighScoreRank>,
+    bindings: Res<'w, Bindings>,
+}
+
 trait Message {
-    fn get_message() -> String;
+    fn get_message(context: &MessageContext) -> String;
 }
 
-fn spawn_message<T: Component + Message + Default>(mut commands: Commands) {
+fn spawn_message<T: Component + Message + Default>(mut commands: Commands, context: MessageContext, config: Res<Config>) {
+    spawn_message_box(&mut commands, &config, MESSAGE_BOX_SIZE, MESSAGE_BOX_FONT_SIZE, T::get_message(&context), T::default(), ());
+}
+
+// A box in the middle of the screen, the markers go on the box and on its text
+pub(crate) fn spawn_message_box(
+    commands: &mut Commands,
+    config: &Config,
+    size: Vec2,
+    font_size: f32,
+    message: String,
+    marker: impl Bundle,
+    text_marker: impl Bundle,
+) {
     commands
         .spawn((
             SpriteBundle {
                 sprite: Sprite {
-                    color: MESSAGE_BOX_BACKGROUND_COLOR,
-                    custom_size: Some(MESSAGE_BOX_SIZE),
+                    color: config.colors.message_box_background.into(),
+                    custom_size: Some(size),
                     ..default()
                 },
                 transform: Transform::from_translation(Vec3::Z),
                 ..default()
             },
-            T::default(),
+            marker,
         ))
         .with_children(|builder| {
             builder.spawn((
                 Text2dBundle {
                     text: Text {
                         sections: vec![TextSection::new(
-                            T::get_message(),
+                            message,
                             TextStyle {
-                                font_size: MESSAGE_BOX_FONT_SIZE,
-                                color: MESSAGE_BOX_TEXT_COLOR,
+                                font_size,
+                                color: config.colors.message_box_text.into(),
                                 ..default()
                             },
                         )],
                         alignment: TextAlignment::Center,
                         ..default()
                     },
-                    text_2d_bounds: Text2dBounds {
-                        size: MESSAGE_BOX_SIZE,
-                    },
+                    text_2d_bounds: Text2dBounds { size },
                     transform: Transform::from_translation(Vec3::Z * Vec3::splat(2.0)),
                     ..default()
                 },
+                text_marker,
             ));
         });
 }
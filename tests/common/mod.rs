@@ -0,0 +1,196 @@
+#![allow(dead_code)]
+
+use std::time::Duration;
+use bevy::{
+    input::{
+        gamepad::{
+            GamepadAxisChangedEvent, GamepadButtonChangedEvent, GamepadConnection, GamepadConnectionEvent,
+            GamepadEvent, GamepadInfo,
+        },
+        keyboard::KeyboardInput,
+        ButtonState,
+        InputPlugin,
+    },
+    prelude::*,
+    time::TimeUpdateStrategy,
+};
+use snake::config::Config;
+use snake::replay::Recording;
+use snake::sim::{Board, Cell, Food, FoodRules, FoodWeights, Rules};
+use snake::snake::{Game, GameState, Scoreboard, Seed, SnakeApp};
+
+pub const SEED: u64 = 42;
+
+const FRAME_DURATION: Duration = Duration::from_millis(10);
+const MAX_FRAMES_PER_TICK: usize = 1000;
+
+// Food rules where only the given food ever shows up
+pub fn only_food(food: Food) -> FoodRules {
+    let mut weights = FoodWeights { mouse: 0, golden_mouse: 0, poison: 0, speed_up: 0, slow_down: 0 };
+    match food {
+        Food::Mouse => weights.mouse = 1,
+        Food::GoldenMouse => weights.golden_mouse = 1,
+        Food::Poison => weights.poison = 1,
+        Food::SpeedUp => weights.speed_up = 1,
+        Food::SlowDown => weights.slow_down = 1,
+    }
+
+    FoodRules { classic: weights, wrap: weights }
+}
+
+// A short game on a small board that eats one mouse before it hits the wall, enough for a high score
+pub fn high_score_rules() -> Rules {
+    Rules {
+        board: Board { width: 10, height: 10 },
+        mouse_spots: vec![Cell::new(2, 0)],
+        food: only_food(Food::Mouse),
+        ..Rules::default()
+    }
+}
+
+// The real game without a window, audio or rendering, driven frame by frame
+pub struct TestApp {
+    pub app: App,
+}
+
+impl TestApp {
+    pub fn new(rules: Rules) -> TestApp {
+        let mut config = Config { rules, ..Config::default() };
+        config.audio.muted = true;
+
+        let mut app = App::new();
+        app.insert_resource(config)
+            .insert_resource(Seed(SEED))
+            .add_plugins((MinimalPlugins, InputPlugin, SnakeApp))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_DURATION));
+
+        // Run the startup systems
+        app.update();
+
+        TestApp { app }
+    }
+
+    // A game that is already running
+    pub fn running(rules: Rules) -> TestApp {
+        let mut test = TestApp::new(rules);
+        test.press(KeyCode::Space);
+
+        assert_eq!(test.state(), GameState::Running);
+
+        test
+    }
+
+    // Presses and releases a key within a single frame, then runs another one to apply any state change
+    pub fn press(&mut self, key: KeyCode) {
+        self.queue_press(key);
+        self.app.update();
+        self.app.update();
+    }
+
+    // Queues a key press that is picked up on the next frame, e.g. by the next tick
+    pub fn queue_press(&mut self, key: KeyCode) {
+        self.send_key(key, ButtonState::Pressed);
+        self.send_key(key, ButtonState::Released);
+    }
+
+    pub fn connect_pad(&mut self, id: usize) {
+        let info = GamepadInfo { name: String::from("Test Pad") };
+        self.send_pad_event(GamepadConnectionEvent::new(Gamepad::new(id), GamepadConnection::Connected(info)));
+        self.app.update();
+    }
+
+    pub fn disconnect_pad(&mut self, id: usize) {
+        self.send_pad_event(GamepadConnectionEvent::new(Gamepad::new(id), GamepadConnection::Disconnected));
+        self.app.update();
+        self.app.update();
+    }
+
+    // Like press, but for a gamepad button
+    pub fn press_button(&mut self, id: usize, button: GamepadButtonType) {
+        self.queue_button(id, button);
+        self.app.update();
+        self.app.update();
+    }
+
+    pub fn queue_button(&mut self, id: usize, button: GamepadButtonType) {
+        self.send_pad_event(GamepadButtonChangedEvent::new(Gamepad::new(id), button, 1.0));
+        self.send_pad_event(GamepadButtonChangedEvent::new(Gamepad::new(id), button, 0.0));
+    }
+
+    // Moves the left stick and leaves it there, picked up on the next frame
+    pub fn queue_stick(&mut self, id: usize, x: f32, y: f32) {
+        self.send_pad_event(GamepadAxisChangedEvent::new(Gamepad::new(id), GamepadAxisType::LeftStickX, x));
+        self.send_pad_event(GamepadAxisChangedEvent::new(Gamepad::new(id), GamepadAxisType::LeftStickY, y));
+    }
+
+    pub fn update(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    // Runs frames until the game has moved exactly once, plus one more to apply any state change
+    pub fn tick(&mut self) {
+        let ticks = self.ticks();
+
+        for _ in 0..MAX_FRAMES_PER_TICK {
+            self.app.update();
+            if self.ticks() != ticks {
+                self.app.update();
+                return;
+            }
+        }
+
+        panic!("the game did not move within {} frames", MAX_FRAMES_PER_TICK);
+    }
+
+    pub fn ticks_until_game_over(&mut self, limit: usize) -> usize {
+        for i in 0..limit {
+            if self.state() == GameState::GameOver {
+                return i;
+            }
+            self.tick();
+        }
+
+        panic!("the game is still running after {} ticks", limit);
+    }
+
+    pub fn state(&self) -> GameState {
+        *self.app.world.resource::<State<GameState>>().get()
+    }
+
+    pub fn game(&self) -> &Game {
+        self.app.world.resource::<Game>()
+    }
+
+    pub fn scoreboard(&self) -> &Scoreboard {
+        self.app.world.resource::<Scoreboard>()
+    }
+
+    // The texts drawn onto the board rather than the UI, like the score pop-ups
+    pub fn board_texts(&mut self) -> Vec<String> {
+        let mut query = self.app.world.query_filtered::<&Text, Without<Node>>();
+        query.iter(&self.app.world).map(|text| text.sections[0].value.clone()).collect()
+    }
+
+    pub fn head(&self, snake: usize) -> Cell {
+        self.game().snake(snake).head()
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.app.world.resource::<Recording>().ticks
+    }
+
+    fn send_pad_event(&mut self, event: impl Into<GamepadEvent>) {
+        self.app.world.send_event(event.into());
+    }
+
+    fn send_key(&mut self, key: KeyCode, state: ButtonState) {
+        self.app.world.send_event(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key),
+            state,
+            window: Entity::PLACEHOLDER,
+        });
+    }
+}
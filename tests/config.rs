@@ -0,0 +1,51 @@
+use snake::config::{Config, ConfigError, Rgb};
+use snake::sim::Board;
+
+// Why the default config with the change is refused
+fn reason(change: impl FnOnce(&mut Config)) -> String {
+    let mut config = Config::default();
+    change(&mut config);
+
+    match config.validate() {
+        Err(ConfigError::Invalid(reason)) => reason,
+        other => panic!("expected an invalid config, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_default_config_is_valid() {
+    assert!(Config::default().validate().is_ok());
+}
+
+#[test]
+fn invalid_rules_are_refused() {
+    assert_eq!(
+        reason(|config| config.rules.snake_starting_length = 0),
+        "rules: snake_starting_length must be at least 1",
+    );
+    assert_eq!(
+        reason(|config| config.rules.board = Board { width: 3, height: 10 }),
+        "rules: board must be at least 4x4, got 3x10",
+    );
+    assert_eq!(
+        reason(|config| config.rules.board = Board { width: 41, height: 22 }),
+        "rules: board width and height must be even, got 41x22",
+    );
+    assert_eq!(
+        reason(|config| config.rules.board = Board { width: 100_000, height: 100_000 }),
+        "rules: board must have at most 4000000 cells, got 100000x100000",
+    );
+    assert_eq!(reason(|config| config.rules.players = 0), "rules: players must be between 1 and 4, got 0");
+    assert!(reason(|config| config.rules.snake_starting_length = 30).starts_with("rules: snake 1 of length 30"));
+}
+
+#[test]
+fn invalid_settings_are_refused() {
+    assert_eq!(reason(|config| config.display.block_size = 0.0), "display.block_size must be positive");
+    assert_eq!(reason(|config| config.input.max_queue_length = 0), "input.max_queue_length must be at least 1");
+    assert_eq!(reason(|config| config.input.stick_dead_zone = 1.0), "input.stick_dead_zone must be between 0 and 1");
+    assert_eq!(
+        reason(|config| config.colors.mouse = Rgb([1.0, 2.0, 0.0])),
+        "colors.mouse components must be between 0 and 1",
+    );
+}
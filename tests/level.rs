@@ -0,0 +1,58 @@
+use std::fs;
+use snake::level::Level;
+use snake::sim::{Board, Cell, Direction, Rules, Spawn};
+
+const LEVEL: &str = "\
+#...*
+..>..
+<...#
+";
+
+#[test]
+fn levels_map_rows_to_cells_around_the_center() {
+    let level = Level::parse(LEVEL).unwrap();
+
+    assert_eq!(level.board, Board { width: 6, height: 4 });
+    assert_eq!(level.walls, vec![Cell::new(-2, 1), Cell::new(2, -1)]);
+    assert_eq!(level.mouse_spots, vec![Cell::new(2, 1)]);
+    assert_eq!((level.spawn, level.direction), (Cell::new(0, 0), Direction::Right));
+    assert_eq!(level.extra_spawns, vec![Spawn { position: Cell::new(-2, -1), direction: Direction::Left }]);
+}
+
+#[test]
+fn levels_survive_a_round_trip_through_text() {
+    let level = Level::parse(LEVEL).unwrap();
+    assert_eq!(level.to_text(), LEVEL);
+
+    let mut rules = Rules { snake_starting_length: 1, players: 2, ..Rules::default() };
+    level.apply(&mut rules);
+    assert_eq!(rules.validate(), Ok(()));
+    assert_eq!(Level::from_rules(&rules), level);
+}
+
+#[test]
+fn shipped_levels_are_valid() {
+    for name in ["assets/levels/pillars.level", "assets/levels/rooms.level"] {
+        let level = Level::parse(&fs::read_to_string(name).unwrap()).unwrap();
+        assert_eq!(Level::parse(&level.to_text()), Ok(level.clone()));
+
+        let mut rules = Rules::default();
+        level.apply(&mut rules);
+        assert_eq!(rules.validate(), Ok(()), "{}", name);
+    }
+}
+
+#[test]
+fn broken_levels_are_refused() {
+    assert_eq!(Level::parse("\n\n"), Err(String::from("level is empty")));
+    assert_eq!(Level::parse("...\n.>\n..."), Err(String::from("row 2 is 2 cells wide, expected 3")));
+    assert_eq!(
+        Level::parse("....\n.>..\n...."),
+        Err(String::from("level must have an odd number of rows and columns, got 4x3")),
+    );
+    assert_eq!(Level::parse("...\n.x.\n..."), Err(String::from("unknown cell `x` at row 2, column 2")));
+    assert_eq!(
+        Level::parse("...\n.#.\n..."),
+        Err(String::from("level has no snake spawn, mark it with > < ^ or v")),
+    );
+}
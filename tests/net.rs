@@ -0,0 +1,141 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use snake::net::{ClientMessage, Connection, Server, ServerEvent, ServerMessage, PROTOCOL_VERSION};
+use snake::sim::{Cell, Direction, Rules};
+
+const SEED: u64 = 42;
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+const MAX_POLLS: usize = 2000;
+
+// A server on localhost, driven by hand with a clock that only moves when told to
+struct TestServer {
+    server: Server,
+    now: Instant,
+    events: Vec<ServerEvent>,
+}
+
+impl TestServer {
+    fn new(rules: Rules) -> TestServer {
+        TestServer {
+            server: Server::bind("127.0.0.1:0", rules, SEED, 2).unwrap(),
+            now: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    fn join(&mut self, name: &str) -> Connection {
+        let mut client = Connection::connect(&self.server.local_addr().unwrap().to_string()).unwrap();
+        client.send(&ClientMessage::Join { version: PROTOCOL_VERSION, name: name.to_string() }).unwrap();
+
+        client
+    }
+
+    // Updates the server until the client gets a message the check accepts
+    fn expect(&mut self, client: &mut Connection, mut check: impl FnMut(&ServerMessage) -> bool) -> ServerMessage {
+        for _ in 0..MAX_POLLS {
+            self.update();
+            let messages: Vec<ServerMessage> = client.receive(self.now).unwrap();
+            if let Some(message) = messages.into_iter().find(|message| check(message)) {
+                return message;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        panic!("the expected message did not arrive");
+    }
+
+    // Gives messages on their way to the server the time to arrive, without moving the clock
+    fn settle(&mut self) {
+        for _ in 0..20 {
+            self.update();
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn tick(&mut self) {
+        self.now += self.server.game().tick_duration();
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let events = self.server.update(self.now);
+        self.events.extend(events);
+    }
+}
+
+fn slot(message: &ServerMessage) -> Result<usize, String> {
+    match message {
+        ServerMessage::Welcome { slot, .. } => Ok(*slot),
+        ServerMessage::Refused(reason) => Err(reason.clone()),
+        _ => unreachable!(),
+    }
+}
+
+fn is_answer(message: &ServerMessage) -> bool {
+    matches!(message, ServerMessage::Welcome { .. } | ServerMessage::Refused(_))
+}
+
+#[test]
+fn clients_take_free_slots_until_the_server_is_full() {
+    let mut test = TestServer::new(Rules { players: 2, ..Rules::default() });
+
+    let mut first = test.join("ada");
+    assert_eq!(slot(&test.expect(&mut first, is_answer)), Ok(0));
+
+    let mut second = test.join("bob");
+    assert_eq!(slot(&test.expect(&mut second, is_answer)), Ok(1));
+
+    let players = test.expect(&mut first, |message| matches!(message, ServerMessage::Players(_)));
+    let ServerMessage::Players(players) = players else { unreachable!() };
+    assert_eq!(players, vec![Some(String::from("ADA")), Some(String::from("BOB"))]);
+
+    let mut third = test.join("cy");
+    assert_eq!(slot(&test.expect(&mut third, is_answer)), Err(String::from("the server is full")));
+}
+
+#[test]
+fn clients_speaking_another_protocol_are_refused() {
+    let mut test = TestServer::new(Rules::default());
+
+    let mut client = Connection::connect(&test.server.local_addr().unwrap().to_string()).unwrap();
+    client.send(&ClientMessage::Join { version: PROTOCOL_VERSION + 1, name: String::from("ada") }).unwrap();
+
+    let answer = slot(&test.expect(&mut client, is_answer));
+    assert!(answer.unwrap_err().starts_with(&format!("protocol version {} is not supported", PROTOCOL_VERSION + 1)));
+    assert!(test.events.is_empty());
+}
+
+#[test]
+fn leaving_frees_the_slot() {
+    let mut test = TestServer::new(Rules::default());
+
+    let mut first = test.join("ada");
+    assert_eq!(slot(&test.expect(&mut first, is_answer)), Ok(0));
+    drop(first);
+    test.settle();
+
+    let mut second = test.join("bob");
+    assert_eq!(slot(&test.expect(&mut second, is_answer)), Ok(0));
+
+    assert_eq!(test.events[0], ServerEvent::Joined { name: String::from("ADA"), slot: 0 });
+    assert!(test.events.iter().any(|event| matches!(event, ServerEvent::Left { slot: 0, .. })));
+    assert_eq!(test.events.last(), Some(&ServerEvent::Joined { name: String::from("BOB"), slot: 0 }));
+}
+
+#[test]
+fn turns_steer_the_snake_of_the_client() {
+    let mut test = TestServer::new(Rules::default());
+
+    let mut client = test.join("ada");
+    test.expect(&mut client, |message| matches!(message, ServerMessage::Snapshot { round: 1, .. }));
+
+    client.send(&ClientMessage::Turn(Direction::Up)).unwrap();
+    test.settle();
+    test.tick();
+
+    let snapshot = test.expect(&mut client, |message| matches!(message, ServerMessage::Snapshot { tick: 1, .. }));
+    let ServerMessage::Snapshot { state, .. } = snapshot else { unreachable!() };
+    assert_eq!(state.snakes[0].head(), Cell::new(0, 1));
+    assert_eq!(test.server.game().snake(0).head(), Cell::new(0, 1));
+}
@@ -0,0 +1,67 @@
+use snake::scores::{format_date, HighScore, HighScores, Table};
+use snake::sim::{Board, GameMode};
+
+fn entry(name: &str, score: usize, mode: GameMode) -> HighScore {
+    HighScore {
+        name: name.to_string(),
+        score,
+        length: 4,
+        difficulty: 0,
+        mode,
+        board: Board::default(),
+        seed: 0,
+        timestamp: 0,
+    }
+}
+
+fn table(mode: GameMode) -> Table {
+    Table { mode, board: Board::default() }
+}
+
+fn names(scores: &HighScores, mode: GameMode) -> Vec<&str> {
+    scores.table(table(mode)).map(|entry| entry.name.as_str()).collect()
+}
+
+#[test]
+fn scores_are_ranked_within_their_table() {
+    let mut scores = HighScores::default();
+    assert_eq!(scores.rank(table(GameMode::Classic), 0), None);
+    assert_eq!(scores.rank(table(GameMode::Classic), 100), Some(0));
+
+    assert_eq!(scores.insert(entry("ADA", 300, GameMode::Classic)), Some(0));
+    assert_eq!(scores.insert(entry("BOB", 500, GameMode::Classic)), Some(0));
+    assert_eq!(scores.insert(entry("CY", 100, GameMode::Wrap)), Some(0));
+    // Ties go below the older score
+    assert_eq!(scores.insert(entry("DAN", 300, GameMode::Classic)), Some(2));
+
+    assert_eq!(names(&scores, GameMode::Classic), vec!["BOB", "ADA", "DAN"]);
+    assert_eq!(names(&scores, GameMode::Wrap), vec!["CY"]);
+    assert_eq!(scores.best(table(GameMode::Classic)), 500);
+    assert_eq!(scores.tables(), vec![table(GameMode::Classic), table(GameMode::Wrap)]);
+}
+
+#[test]
+fn full_tables_drop_their_lowest_score() {
+    let mut scores = HighScores::default();
+    for i in 1..=10 {
+        scores.insert(entry(&i.to_string(), i * 100, GameMode::Classic));
+    }
+    scores.insert(entry("WRAP", 50, GameMode::Wrap));
+
+    assert_eq!(scores.rank(table(GameMode::Classic), 100), None);
+    assert_eq!(scores.insert(entry("LOW", 50, GameMode::Classic)), None);
+
+    assert_eq!(scores.insert(entry("HIGH", 550, GameMode::Classic)), Some(5));
+    let classic = names(&scores, GameMode::Classic);
+    assert_eq!(classic.len(), 10);
+    assert_eq!(classic[5], "HIGH");
+    assert_eq!(classic.last(), Some(&"2"));
+    // Other tables keep their scores
+    assert_eq!(names(&scores, GameMode::Wrap), vec!["WRAP"]);
+}
+
+#[test]
+fn dates_are_formatted_in_utc() {
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(951_782_400), "2000-02-29");
+}
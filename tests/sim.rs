@@ -0,0 +1,212 @@
+mod common;
+
+use common::SEED;
+use snake::ai;
+use snake::replay::Replay;
+use snake::sim::{Cell, Direction, Food, GameMode, PowerUp, PowerUpItem, RivalBehaviour, Rules, SnakeSim, StepOutcome};
+
+// A game with the power-up lying right in front of the first snake
+fn with_power_up(rules: Rules, power_up: PowerUp) -> SnakeSim {
+    let sim = SnakeSim::new(rules.clone(), SEED);
+    let mut snapshot = sim.snapshot();
+    let cell = sim.neighbour(sim.snake(0).head(), sim.snake(0).direction());
+    snapshot.power_up = Some(PowerUpItem { cell, power_up, ticks_left: rules.power_up_duration });
+
+    SnakeSim::from_snapshot(rules, snapshot)
+}
+
+// Steps straight on until the first snake's head is at the cell
+fn step_to(sim: &mut SnakeSim, cell: Cell) {
+    while sim.snake(0).head() != cell {
+        assert!(sim.snake(0).is_alive());
+        sim.step(&[None]);
+    }
+}
+
+// The players steered by the autopilot and the rivals by their behaviour, like in the game
+fn computer_inputs(sim: &SnakeSim) -> Vec<Option<Direction>> {
+    let rules = sim.rules();
+
+    (0..rules.snake_count())
+        .map(|i| if rules.is_rival(i) { ai::choose_direction(sim, i, rules.rival_behaviour) } else { ai::autopilot_direction(sim, i) })
+        .collect()
+}
+
+#[test]
+fn snakes_die_on_the_edge_in_classic_mode() {
+    let mut sim = SnakeSim::new(Rules { mouse_spots: vec![Cell::new(0, 5)], ..Rules::default() }, SEED);
+
+    step_to(&mut sim, Cell::new(19, 0));
+    assert_eq!(sim.step(&[None]), vec![(0, StepOutcome::Died)]);
+    assert!(sim.is_game_over());
+}
+
+#[test]
+fn snakes_go_through_the_edge_in_wrap_mode() {
+    let rules = Rules { mode: GameMode::Wrap, mouse_spots: vec![Cell::new(0, 5)], ..Rules::default() };
+    let mut sim = SnakeSim::new(rules, SEED);
+
+    step_to(&mut sim, Cell::new(19, 0));
+    assert_eq!(sim.step(&[None]), vec![(0, StepOutcome::Moved)]);
+    assert_eq!(sim.snake(0).head(), Cell::new(-19, 0));
+
+    sim.step(&[Some(Direction::Down)]);
+    step_to(&mut sim, Cell::new(-19, -10));
+    sim.step(&[None]);
+    assert_eq!(sim.snake(0).head(), Cell::new(-19, 10));
+    assert_eq!(ai::distance(&sim, Cell::new(-19, 10), Cell::new(19, -10)), 2);
+}
+
+#[test]
+fn rivals_spawn_after_the_players_and_chase_the_mouse() {
+    let rules = Rules {
+        rivals: 1,
+        mouse_spots: vec![Cell::new(-3, -4)],
+        food: common::only_food(Food::Mouse),
+        ..Rules::default()
+    };
+    let mut sim = SnakeSim::new(rules, SEED);
+    assert!(sim.rules().is_rival(1));
+    assert_eq!(sim.snake(1).head(), Cell::new(0, -4));
+    assert_eq!(sim.snake(1).direction(), Direction::Left);
+
+    for _ in 0..3 {
+        let inputs = vec![None, ai::choose_direction(&sim, 1, RivalBehaviour::Greedy)];
+        sim.step(&inputs);
+    }
+
+    assert_eq!(sim.snake(1).score(), 100);
+    assert_eq!(sim.snake(1).body().len(), 5);
+    assert_eq!(sim.snake(0).score(), 0);
+    // Rivals do not count towards the difficulty
+    assert_eq!(sim.difficulty(), 0);
+}
+
+#[test]
+fn shortest_path_goes_around_walls() {
+    let rules = Rules {
+        walls: (-2..=2).map(|y| Cell::new(1, y)).collect(),
+        mouse_spots: vec![Cell::new(-10, 8)],
+        ..Rules::default()
+    };
+    let mut sim = SnakeSim::new(rules, SEED);
+    let target = Cell::new(3, 0);
+
+    let mut steps = 0;
+    while sim.snake(0).head() != target {
+        let direction = ai::shortest_path(&sim, 0, target).unwrap();
+        sim.step(&[Some(direction)]);
+        steps += 1;
+    }
+
+    assert_eq!(steps, 9);
+    assert!(sim.snake(0).is_alive());
+}
+
+#[test]
+fn the_autopilot_keeps_eating_without_crashing() {
+    let mut sim = SnakeSim::new(Rules { food: common::only_food(Food::Mouse), ..Rules::default() }, SEED);
+
+    for _ in 0..500 {
+        let inputs = computer_inputs(&sim);
+        sim.step(&inputs);
+    }
+
+    assert!(sim.snake(0).is_alive());
+    assert!(sim.score() >= 1000);
+}
+
+#[test]
+fn replays_play_back_the_same_game() {
+    let rules = Rules { rivals: 2, rival_behaviour: RivalBehaviour::Cautious, ..Rules::default() };
+    let mut sim = SnakeSim::new(rules.clone(), SEED);
+    let mut replay = Replay::new(rules, SEED);
+
+    while !sim.is_game_over() && replay.ticks < 1000 {
+        let inputs = computer_inputs(&sim);
+        replay.record(&sim, &inputs);
+        sim.step(&inputs);
+    }
+    assert!(!replay.turns.is_empty());
+
+    let played = replay.play_to(replay.ticks);
+    assert_eq!(played.is_game_over(), sim.is_game_over());
+    assert_eq!(played.mouse(), sim.mouse());
+    assert_eq!(played.difficulty(), sim.difficulty());
+    for (played, snake) in played.snakes().iter().zip(sim.snakes()) {
+        assert_eq!(played.body(), snake.body());
+        assert_eq!(played.score(), snake.score());
+        assert_eq!(played.is_alive(), snake.is_alive());
+    }
+
+    // The same rules with another seed make another game
+    let other = Replay { seed: SEED + 1, ..replay.clone() }.play_to(replay.ticks);
+    assert_ne!(other.snake(0).body(), sim.snake(0).body());
+}
+
+#[test]
+fn a_shield_turns_the_snake_away_from_a_wall() {
+    let rules = Rules { mouse_spots: vec![Cell::new(0, 5)], food: common::only_food(Food::Mouse), ..Rules::default() };
+    let mut sim = with_power_up(rules, PowerUp::Shield);
+    assert_eq!(sim.step(&[None]), vec![(0, StepOutcome::PickedUp(PowerUp::Shield))]);
+
+    step_to(&mut sim, Cell::new(19, 0));
+    assert_eq!(sim.step(&[None]), vec![(0, StepOutcome::Shielded)]);
+    assert!(matches!(sim.snake(0).direction(), Direction::Up | Direction::Down));
+    assert!(sim.snake(0).is_alive());
+    assert!(!sim.snake(0).has_effect(PowerUp::Shield));
+}
+
+#[test]
+fn a_boxed_in_shielded_snake_waits_a_tick() {
+    let rules = Rules {
+        mouse_spots: vec![Cell::new(0, 5)],
+        food: common::only_food(Food::Mouse),
+        walls: vec![Cell::new(19, 1), Cell::new(19, -1)],
+        ..Rules::default()
+    };
+    let mut sim = with_power_up(rules, PowerUp::Shield);
+    sim.step(&[None]);
+
+    step_to(&mut sim, Cell::new(19, 0));
+    let body = sim.snake(0).body().clone();
+    assert_eq!(sim.step(&[None]), vec![(0, StepOutcome::Shielded)]);
+    assert_eq!(sim.snake(0).body(), &body);
+    assert!(sim.snake(0).is_alive());
+    assert!(!sim.snake(0).has_effect(PowerUp::Shield));
+
+    // Without the shield the next hit kills
+    assert_eq!(sim.step(&[None]), vec![(0, StepOutcome::Died)]);
+}
+
+#[test]
+fn a_magnet_pulls_the_mouse_across_the_edge_in_wrap_mode() {
+    let rules = Rules {
+        mode: GameMode::Wrap,
+        snake_starting_position: Cell::new(15, 0),
+        mouse_spots: vec![Cell::new(-17, 0)],
+        food: common::only_food(Food::Mouse),
+        ..Rules::default()
+    };
+    let mut sim = with_power_up(rules, PowerUp::Magnet);
+
+    // The head is 6 cells away through the edge, and 33 the other way
+    sim.step(&[None]);
+    assert_eq!(sim.mouse(), Cell::new(-18, 0));
+}
+
+#[test]
+fn a_magnet_leaves_other_foods_where_they_are() {
+    let rules = Rules {
+        snake_starting_position: Cell::new(-5, 0),
+        mouse_spots: vec![Cell::new(0, 3)],
+        food: common::only_food(Food::Poison),
+        ..Rules::default()
+    };
+    let mut sim = with_power_up(rules, PowerUp::Magnet);
+
+    // Poison is in range, but only mice get pulled in
+    sim.step(&[None]);
+    assert!(sim.snake(0).has_effect(PowerUp::Magnet));
+    assert_eq!(sim.mouse(), Cell::new(0, 3));
+}
@@ -0,0 +1,469 @@
+mod common;
+
+use std::time::Duration;
+use bevy::prelude::*;
+use common::TestApp;
+use snake::bindings::{Action, Bindings, BindingsPath};
+use snake::scores::{HighScores, HighScoresPath, Table};
+use snake::ai;
+use snake::sim::{Board, Cell, DifficultyPreset, Direction, Food, MouseBehaviour, Rules};
+use snake::snake::{Autopilot, GameState};
+
+#[test]
+fn space_starts_and_pauses_the_game() {
+    let mut test = TestApp::new(Rules::default());
+    assert_eq!(test.state(), GameState::Startup);
+
+    test.press(KeyCode::Space);
+    assert_eq!(test.state(), GameState::Running);
+
+    test.press(KeyCode::Space);
+    assert_eq!(test.state(), GameState::Paused);
+
+    // Nothing moves while paused
+    let head = test.head(0);
+    test.update(100);
+    assert_eq!(test.head(0), head);
+
+    test.press(KeyCode::Space);
+    assert_eq!(test.state(), GameState::Running);
+}
+
+#[test]
+fn snake_moves_one_cell_per_tick() {
+    let mut test = TestApp::running(Rules::default());
+
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(1, 0));
+
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(2, 0));
+    assert_eq!(test.game().snake(0).body().len(), 4);
+}
+
+#[test]
+fn keys_turn_the_snake() {
+    let mut test = TestApp::running(Rules::default());
+
+    test.queue_press(KeyCode::W);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(0, 1));
+
+    test.queue_press(KeyCode::Left);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(-1, 1));
+    assert_eq!(test.game().snake(0).direction(), Direction::Left);
+}
+
+#[test]
+fn snake_cannot_reverse_into_itself() {
+    let mut test = TestApp::running(Rules::default());
+
+    test.queue_press(KeyCode::A);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(1, 0));
+    assert_eq!(test.state(), GameState::Running);
+}
+
+#[test]
+fn queued_turns_are_used_on_consecutive_ticks() {
+    let mut test = TestApp::running(Rules::default());
+
+    // Keys pressed within the same frame come in no particular order, so use separate frames
+    test.queue_press(KeyCode::W);
+    test.update(1);
+    test.queue_press(KeyCode::A);
+    test.tick();
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(-1, 1));
+}
+
+#[test]
+fn eating_a_mouse_grows_the_snake_and_scores() {
+    let rules = Rules { mouse_spots: vec![Cell::new(2, 0)], food: common::only_food(Food::Mouse), ..Rules::default() };
+    let mut test = TestApp::running(rules);
+    assert_eq!(test.game().mouse(), Cell::new(2, 0));
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.scoreboard().score, 100);
+    assert_eq!(test.game().snake(0).body().len(), 5);
+    assert_ne!(test.game().mouse(), Cell::new(2, 0));
+}
+
+#[test]
+fn eating_in_quick_succession_builds_a_combo() {
+    let rules = Rules {
+        mouse_spots: vec![Cell::new(4, 0), Cell::new(2, 0)],
+        food: common::only_food(Food::Mouse),
+        ..Rules::default()
+    };
+    let mut test = TestApp::running(rules);
+    assert_eq!(test.game().mouse(), Cell::new(2, 0));
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.game().snake(0).combo(), 1);
+    assert_eq!(test.scoreboard().score, 100);
+
+    // The second mouse has the one grown segment on top of the combo
+    test.tick();
+    test.tick();
+    assert_eq!(test.game().snake(0).combo(), 2);
+    assert_eq!(test.scoreboard().score, 100 + 204);
+}
+
+#[test]
+fn combos_stop_multiplying_at_the_max_combo() {
+    let rules = Rules { board: Board { width: 10, height: 10 }, food: common::only_food(Food::Mouse), max_combo: 2, ..Rules::default() };
+    let mut test = TestApp::running(rules);
+    test.app.insert_resource(Autopilot(true));
+
+    let mut points = 0;
+    while test.game().snake(0).combo() < 3 {
+        let score = test.scoreboard().score;
+        test.tick();
+        points = test.scoreboard().score - score;
+    }
+
+    assert_eq!(test.game().combo_multiplier(0), 2);
+    assert!(test.board_texts().contains(&format!("+{} x2", points)));
+}
+
+#[test]
+fn poison_shrinks_the_snake_without_scoring() {
+    let rules = Rules { mouse_spots: vec![Cell::new(2, 0)], food: common::only_food(Food::Poison), ..Rules::default() };
+    let mut test = TestApp::running(rules);
+    assert_eq!(test.game().food(), Food::Poison);
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.scoreboard().score, 0);
+    assert_eq!(test.game().snake(0).body().len(), 1);
+    assert_eq!(test.state(), GameState::Running);
+}
+
+#[test]
+fn speed_up_food_makes_the_game_faster() {
+    let rules = Rules { mouse_spots: vec![Cell::new(2, 0)], food: common::only_food(Food::SpeedUp), ..Rules::default() };
+    let mut test = TestApp::running(rules.clone());
+    let duration = test.game().tick_duration();
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.scoreboard().score, 100);
+    assert_eq!(test.game().difficulty(), rules.starting_difficulty);
+    assert!(test.game().tick_duration() < duration);
+}
+
+#[test]
+fn ticks_stop_getting_shorter_at_the_minimum() {
+    let mut test = TestApp::running(Rules { starting_difficulty: 100, ..Rules::default() });
+    let minimum = Duration::from_secs_f32(test.game().rules().timer_min_duration);
+    assert_eq!(test.game().tick_duration(), minimum);
+
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(1, 0));
+}
+
+#[test]
+fn difficulty_presets_are_picked_before_the_game_starts() {
+    let mut test = TestApp::new(Rules::default());
+    assert_eq!(DifficultyPreset::of(test.game().rules()), Some(DifficultyPreset::Normal));
+
+    test.press(KeyCode::Tab);
+    assert_eq!(DifficultyPreset::of(test.game().rules()), Some(DifficultyPreset::Hard));
+    assert_eq!(test.game().difficulty(), 2);
+
+    test.press(KeyCode::Tab);
+    test.press(KeyCode::Tab);
+    assert_eq!(DifficultyPreset::of(test.game().rules()), Some(DifficultyPreset::Easy));
+
+    // The preset stays once the game is running
+    test.press(KeyCode::Space);
+    test.press(KeyCode::Tab);
+    assert_eq!(test.state(), GameState::Running);
+    assert_eq!(DifficultyPreset::of(test.game().rules()), Some(DifficultyPreset::Easy));
+}
+
+#[test]
+fn a_bonus_shows_up_for_a_while() {
+    let rules = Rules {
+        mouse_spots: vec![Cell::new(2, 0)],
+        food: common::only_food(Food::Mouse),
+        bonus_chance: 100,
+        bonus_lifetime: 3,
+        ..Rules::default()
+    };
+    let mut test = TestApp::running(rules);
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.game().bonus().map(|bonus| bonus.ticks_left), Some(3));
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.game().bonus().map(|bonus| bonus.ticks_left), Some(1));
+
+    test.tick();
+    assert_eq!(test.game().bonus(), None);
+    assert!(test.game().bonus_expired());
+    assert_eq!(test.scoreboard().score, 100);
+}
+
+#[test]
+fn picking_up_a_power_up_starts_its_effect() {
+    let rules = Rules {
+        mouse_spots: vec![Cell::new(2, 0)],
+        food: common::only_food(Food::Mouse),
+        bonus_chance: 0,
+        power_up_chance: 100,
+        ..Rules::default()
+    };
+    let mut test = TestApp::running(rules.clone());
+    test.tick();
+    test.tick();
+    let item = test.game().power_up().unwrap();
+
+    while test.game().power_up().is_some() {
+        let key = match ai::shortest_path(test.game(), 0, item.cell).unwrap() {
+            Direction::Up => KeyCode::W,
+            Direction::Down => KeyCode::S,
+            Direction::Left => KeyCode::A,
+            Direction::Right => KeyCode::D,
+        };
+        test.queue_press(key);
+        test.tick();
+    }
+
+    let effects = test.game().snake(0).effects();
+    assert_eq!(effects.len(), 1);
+    assert_eq!(effects[0].power_up, item.power_up);
+    assert_eq!(effects[0].ticks_left, rules.power_up_duration);
+}
+
+#[test]
+fn sly_mice_run_from_the_head() {
+    let rules = Rules {
+        mouse_spots: vec![Cell::new(3, 0)],
+        food: common::only_food(Food::Mouse),
+        mouse_behaviour: MouseBehaviour::Sly,
+        ..Rules::default()
+    };
+    let mut test = TestApp::running(rules);
+
+    test.tick();
+    assert_eq!(test.game().mouse(), Cell::new(3, 0));
+
+    test.tick();
+    assert_ne!(test.game().mouse(), Cell::new(3, 0));
+    assert_eq!(test.game().distance(test.head(0), test.game().mouse()), 2);
+    assert_eq!(test.scoreboard().score, 0);
+}
+
+#[test]
+fn poison_stays_put_whatever_the_mice_do() {
+    let rules = Rules {
+        mouse_spots: vec![Cell::new(3, 0)],
+        food: common::only_food(Food::Poison),
+        mouse_behaviour: MouseBehaviour::Sly,
+        ..Rules::default()
+    };
+    let mut test = TestApp::running(rules);
+
+    test.tick();
+    test.tick();
+    assert_eq!(test.game().mouse(), Cell::new(3, 0));
+
+    test.tick();
+    assert_eq!(test.game().snake(0).body().len(), 1);
+}
+
+#[test]
+fn hitting_a_wall_ends_the_game() {
+    let rules = Rules { board: Board { width: 10, height: 10 }, ..Rules::default() };
+    let mut test = TestApp::running(rules);
+
+    // The wall is at x = 5, the head starts at x = 0
+    assert_eq!(test.ticks_until_game_over(10), 5);
+    assert_eq!(test.scoreboard().score, 0);
+    assert!(test.game().is_game_over());
+}
+
+#[test]
+fn hitting_itself_ends_the_game() {
+    let rules = Rules { snake_starting_length: 5, ..Rules::default() };
+    let mut test = TestApp::running(rules);
+
+    for key in [KeyCode::W, KeyCode::A, KeyCode::S] {
+        test.queue_press(key);
+        test.tick();
+    }
+
+    assert_eq!(test.state(), GameState::GameOver);
+}
+
+#[test]
+fn r_restarts_after_game_over() {
+    let rules = Rules { board: Board { width: 10, height: 10 }, ..Rules::default() };
+    let mut test = TestApp::running(rules);
+    test.ticks_until_game_over(10);
+
+    test.press(KeyCode::R);
+    test.update(1);
+    assert_eq!(test.state(), GameState::Running);
+    assert_eq!(test.head(0), Cell::new(0, 0));
+    assert_eq!(test.scoreboard().score, 0);
+}
+
+#[test]
+fn second_player_steers_with_the_arrow_keys() {
+    let rules = Rules { players: 2, ..Rules::default() };
+    let mut test = TestApp::running(rules);
+    let (first, second) = (test.head(0), test.head(1));
+
+    test.queue_press(KeyCode::Down);
+    test.tick();
+    assert_eq!(test.head(0), first.step(Direction::Right));
+    assert_eq!(test.head(1), second.step(Direction::Down));
+    assert_eq!(test.scoreboard().scores, vec![0, 0]);
+}
+
+#[test]
+fn head_to_head_collision_is_a_draw() {
+    let rules = Rules { players: 2, ..Rules::default() };
+    let mut test = TestApp::running(rules);
+
+    // The snakes start on parallel lanes four rows apart, steer them into each other
+    test.queue_press(KeyCode::S);
+    test.queue_press(KeyCode::Up);
+    test.tick();
+    test.tick();
+    assert_eq!(test.state(), GameState::GameOver);
+    assert_eq!(test.game().winner(), None);
+}
+
+#[test]
+fn gamepads_start_and_steer_the_game() {
+    let mut test = TestApp::new(Rules::default());
+    test.connect_pad(0);
+
+    test.press_button(0, GamepadButtonType::Start);
+    assert_eq!(test.state(), GameState::Running);
+
+    test.queue_button(0, GamepadButtonType::DPadUp);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(0, 1));
+
+    // A stick inside the dead zone does nothing, past it the snake turns once
+    test.queue_stick(0, -0.2, 0.0);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(0, 2));
+    test.queue_stick(0, -0.9, 0.1);
+    test.tick();
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(-2, 2));
+
+    test.press_button(0, GamepadButtonType::Start);
+    assert_eq!(test.state(), GameState::Paused);
+}
+
+#[test]
+fn unplugging_a_gamepad_pauses_the_game() {
+    let mut test = TestApp::running(Rules::default());
+    test.connect_pad(0);
+
+    test.disconnect_pad(0);
+    assert_eq!(test.state(), GameState::Paused);
+}
+
+#[test]
+fn rebound_keys_steer_the_snake() {
+    let path = std::env::temp_dir().join(format!("snake-bindings-{}.toml", std::process::id()));
+    let mut test = TestApp::new(Rules::default());
+    test.app.insert_resource(BindingsPath(path.clone()));
+
+    test.press(KeyCode::B);
+    assert_eq!(test.state(), GameState::Bindings);
+
+    // Player 1 up is the first action on the screen
+    test.press(KeyCode::Return);
+    test.press(KeyCode::I);
+    test.press(KeyCode::Escape);
+    assert_eq!(test.state(), GameState::Startup);
+
+    let saved = Bindings::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.keys(Action::TurnUp), [KeyCode::I]);
+
+    test.press(KeyCode::Space);
+    test.queue_press(KeyCode::W);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(1, 0));
+
+    test.queue_press(KeyCode::I);
+    test.tick();
+    assert_eq!(test.head(0), Cell::new(1, 1));
+}
+
+#[test]
+fn game_over_records_a_high_score() {
+    let rules = common::high_score_rules();
+    let mut test = TestApp::running(rules.clone());
+    test.ticks_until_game_over(10);
+
+    let high_scores = test.app.world.resource::<HighScores>();
+    assert_eq!(high_scores.best(Table::from(&rules)), 100);
+    assert_eq!(high_scores.entries[0].length, 5);
+    assert_eq!(high_scores.entries[0].seed, common::SEED);
+}
+
+#[test]
+fn new_high_score_asks_for_a_name() {
+    let rules = common::high_score_rules();
+    let path = std::env::temp_dir().join(format!("snake-high-scores-{}.toml", std::process::id()));
+    let mut test = TestApp::running(rules);
+    test.app.insert_resource(HighScoresPath(path.clone()));
+
+    while test.state() == GameState::Running {
+        test.tick();
+    }
+    assert_eq!(test.state(), GameState::NameEntry);
+
+    test.press(KeyCode::Up);
+    test.press(KeyCode::Right);
+    test.press(KeyCode::Down);
+    test.press(KeyCode::Return);
+    assert_eq!(test.state(), GameState::GameOver);
+
+    let saved = HighScores::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.entries[0].name, "BZA");
+    assert_eq!(saved.entries[0].score, 100);
+}
+
+#[test]
+fn rebound_keys_also_enter_names() {
+    let rules = common::high_score_rules();
+    let path = std::env::temp_dir().join(format!("snake-rebound-high-scores-{}.toml", std::process::id()));
+    let mut test = TestApp::running(rules);
+    test.app.insert_resource(HighScoresPath(path.clone()));
+    let mut bindings = test.app.world.resource_mut::<Bindings>();
+    bindings.bind(Action::SecondTurnUp, KeyCode::I);
+    bindings.bind(Action::Confirm, KeyCode::K);
+
+    while test.state() == GameState::Running {
+        test.tick();
+    }
+    assert_eq!(test.state(), GameState::NameEntry);
+
+    test.press(KeyCode::I);
+    test.press(KeyCode::Return);
+    assert_eq!(test.state(), GameState::NameEntry);
+    test.press(KeyCode::K);
+    assert_eq!(test.state(), GameState::GameOver);
+
+    let saved = HighScores::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.entries[0].name, "BAA");
+}